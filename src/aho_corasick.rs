@@ -0,0 +1,319 @@
+/* Aho-Corasick finds any of a set of literal strings in a single pass over the input, no matter how many
+ * literals there are. The literals are put in a trie, and each node of the trie gets a failure link to the
+ * node for the longest proper suffix of its string that's also in the trie. Whenever the next character
+ * doesn't continue the current node's string we follow failure links until one does (or we're back at the
+ * root), so we never have to back up in the input.
+ *
+ * Each node also gets an output link to the closest node along its failure links that ends a literal, so
+ * the literals ending at the current position can be found without walking the whole failure chain. We
+ * only need the first of them though, since it's the longest and so the one that starts furthest left.
+ *
+ * Link: https://cr.yp.to/bib/1975/aho.pdf
+ */
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    case_fold::canonical_fold,
+    graph::{Graph, NodeIndex},
+};
+
+pub struct AhoCorasick {
+    trie: Graph<TrieState, char>,
+    root: NodeIndex,
+    // Nearly every step goes through the root, so it gets a proper lookup table rather than a scan of its edges
+    root_transitions: HashMap<char, NodeIndex>,
+    literals: Vec<String>,
+    ignore_case: bool,
+}
+
+struct TrieState {
+    // Length in characters of the string this node stands for
+    depth: usize,
+    // Index of the literal that ends here, if any
+    literal: Option<usize>,
+    fail: NodeIndex,
+    output: Option<NodeIndex>,
+}
+
+impl AhoCorasick {
+    pub fn new(literals: &[String], ignore_case: bool) -> AhoCorasick {
+        let mut trie: Graph<TrieState, char> = Graph::new();
+        let root = trie.add_node(TrieState::new(0));
+
+        let mut automaton = AhoCorasick {
+            trie,
+            root,
+            root_transitions: HashMap::new(),
+            literals: literals.to_vec(),
+            ignore_case,
+        };
+
+        for (index, literal) in literals.iter().enumerate() {
+            automaton.insert(index, literal);
+        }
+
+        automaton.build_links();
+
+        return automaton;
+    }
+
+    pub fn get_literal(&self, index: usize) -> Option<&str> {
+        return self.literals.get(index).map(|literal| literal.as_str());
+    }
+
+    // Returns the byte range of the leftmost-longest literal in the haystack, along with its index
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize, usize)> {
+        // Start offsets of the most recent characters, enough to cover the longest possible match
+        let mut positions: VecDeque<usize> = VecDeque::new();
+        let mut state = self.root;
+        let mut seen = 0;
+
+        // (start character index, start, end, literal)
+        let mut best: Option<(usize, usize, usize, usize)> =
+            self.match_at(state, &positions, 0, seen);
+
+        for (i, c) in haystack.char_indices() {
+            let depth = self.get_depth(state);
+
+            if positions.len() > depth {
+                positions.drain(..positions.len() - depth);
+            }
+
+            positions.push_back(i);
+            seen += 1;
+            state = self.next_state(state, self.fold(c));
+
+            let end = i + c.len_utf8();
+
+            if let Some(found) = self.match_at(state, &positions, end, seen) {
+                let better = match best {
+                    Some((start, _, _, _)) => found.0 <= start,
+                    None => true,
+                };
+
+                if better {
+                    best = Some(found);
+                }
+            }
+
+            // Anything found from here on starts at or after the start of the current node's string
+            if let Some((start, _, _, _)) = best {
+                if start < seen - self.get_depth(state) {
+                    break;
+                }
+            }
+        }
+
+        return best.map(|(_, start, end, literal)| (start, end, literal));
+    }
+
+    fn match_at(
+        &self,
+        state: NodeIndex,
+        positions: &VecDeque<usize>,
+        end: usize,
+        seen: usize,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let data = self.trie.get_node_data(&state).unwrap();
+
+        let found = match data.literal {
+            Some(_) => state,
+            None => data.output?,
+        };
+
+        let found = self.trie.get_node_data(&found).unwrap();
+
+        let start = match found.depth {
+            0 => end,
+            depth => positions[positions.len() - depth],
+        };
+
+        return Some((seen - found.depth, start, end, found.literal.unwrap()));
+    }
+
+    fn next_state(&self, mut state: NodeIndex, c: char) -> NodeIndex {
+        loop {
+            if let Some(next) = self.goto(state, c) {
+                return next;
+            }
+
+            if state == self.root {
+                return self.root;
+            }
+
+            state = self.trie.get_node_data(&state).unwrap().fail;
+        }
+    }
+
+    fn goto(&self, state: NodeIndex, c: char) -> Option<NodeIndex> {
+        if state == self.root {
+            return self.root_transitions.get(&c).copied();
+        }
+
+        let outgoing_edges = self.trie.outgoing_edges(state).unwrap();
+
+        for edge in outgoing_edges {
+            if *self.trie.get_edge_data(&edge).unwrap() == c {
+                return Some(self.trie.traverse(edge).unwrap());
+            }
+        }
+
+        return None;
+    }
+
+    fn insert(&mut self, index: usize, literal: &str) {
+        let mut state = self.root;
+
+        for c in literal.chars() {
+            let c = self.fold(c);
+
+            state = match self.goto(state, c) {
+                Some(next) => next,
+                None => {
+                    let depth = self.get_depth(state) + 1;
+                    let next = self.trie.add_node(TrieState::new(depth));
+
+                    self.trie.add_edge(state, next, c);
+
+                    if state == self.root {
+                        self.root_transitions.insert(c, next);
+                    }
+
+                    next
+                }
+            };
+        }
+
+        let data = self.trie.get_node_data_mut(&state).unwrap();
+
+        // The same literal twice reports the first one
+        if data.literal.is_none() {
+            data.literal = Some(index);
+        }
+    }
+
+    // Breadth first, so the failure links of every shallower node are known by the time we need them
+    fn build_links(&mut self) {
+        let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+        queue.push_back(self.root);
+
+        while let Some(state) = queue.pop_front() {
+            let outgoing_edges = self.trie.outgoing_edges(state).unwrap();
+
+            for edge in outgoing_edges {
+                let c = *self.trie.get_edge_data(&edge).unwrap();
+                let child = self.trie.traverse(edge).unwrap();
+
+                let fail = if state == self.root {
+                    self.root
+                } else {
+                    let parent_fail = self.trie.get_node_data(&state).unwrap().fail;
+                    self.next_state(parent_fail, c)
+                };
+
+                let fail_data = self.trie.get_node_data(&fail).unwrap();
+
+                let output = match fail_data.literal {
+                    Some(_) => Some(fail),
+                    None => fail_data.output,
+                };
+
+                let child_data = self.trie.get_node_data_mut(&child).unwrap();
+
+                child_data.fail = fail;
+                child_data.output = output;
+
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn get_depth(&self, state: NodeIndex) -> usize {
+        return self.trie.get_node_data(&state).unwrap().depth;
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.ignore_case {
+            return canonical_fold(c);
+        }
+
+        return c;
+    }
+}
+
+impl TrieState {
+    fn new(depth: usize) -> TrieState {
+        return TrieState {
+            depth,
+            literal: None,
+            fail: 0,
+            output: None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build(literals: &[&str], ignore_case: bool) -> AhoCorasick {
+        let literals: Vec<String> = literals.iter().map(|s| s.to_string()).collect();
+
+        return AhoCorasick::new(&literals, ignore_case);
+    }
+
+    #[test]
+    fn given_literals_when_finding_should_return_the_leftmost_longest_one_and_which_it_was() {
+        let examples = [
+            (vec!["he", "she", "his", "hers"], "ushers", Some((1, 4, 1))),
+            (vec!["he", "she", "his", "hers"], "uhers", Some((1, 5, 3))),
+            (vec!["abcd", "bc"], "abcx", Some((1, 3, 1))),
+            (vec!["b", "abcd"], "abcd", Some((0, 4, 1))),
+            (vec!["ab", "bcde"], "abcde", Some((0, 2, 0))),
+            (vec!["abcd", "c"], "abcd", Some((0, 4, 0))),
+            (
+                vec!["you", "us"],
+                "They'd banish us, you know.",
+                Some((14, 16, 1)),
+            ),
+            (vec!["café", "fé!"], "un café!", Some((3, 8, 0))),
+            (vec!["foo", "bar"], "baz", None),
+        ];
+
+        for (literals, haystack, answer) in examples {
+            assert_eq!(
+                build(&literals, false).find(haystack),
+                answer,
+                "{}",
+                haystack
+            );
+        }
+    }
+
+    #[test]
+    fn given_many_literals_when_finding_should_check_them_all_in_one_pass() {
+        let words: Vec<String> = (0..5000).map(|i| format!("token{}x", i)).collect();
+        let automaton = AhoCorasick::new(&words, false);
+
+        assert_eq!(automaton.find("a token4321x here"), Some((2, 12, 4321)));
+        assert_eq!(automaton.get_literal(4321), Some("token4321x"));
+        assert_eq!(automaton.find("token5000x"), None);
+    }
+
+    #[test]
+    fn given_ignore_case_when_finding_should_fold_both_the_literals_and_the_haystack() {
+        let automaton = build(&["rust", "σοφια"], true);
+
+        assert_eq!(automaton.find("Trust me"), Some((1, 5, 0)));
+        assert_eq!(automaton.find("ΣΟΦΙΑ"), Some((0, 10, 1)));
+        // The Kelvin sign takes 3 bytes, the k it folds to only takes 1
+        assert_eq!(build(&["ok"], true).find("O\u{212A}!"), Some((0, 4, 0)));
+    }
+
+    #[test]
+    fn given_empty_literal_when_finding_should_match_at_the_start() {
+        assert_eq!(build(&["", "abc"], false).find("abc"), Some((0, 3, 1)));
+        assert_eq!(build(&["", "abc"], false).find("xyz"), Some((0, 0, 0)));
+    }
+}
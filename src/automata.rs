@@ -1,13 +1,25 @@
+use std::{collections::BTreeSet, sync::Arc};
+
 use crate::graph::NodeIndex;
 
+// Index of a pattern in the list an automaton was built from, see RegexSet
+pub type PatternID = usize;
+
 pub struct AutomataState {
     accepting: bool,
+    // Which patterns accept in this state, kept sorted so they come out in order
+    patterns: BTreeSet<PatternID>,
 }
 
+#[derive(Clone)]
 pub struct AutomataLabel {
     label: Option<char>,
+    // When set, the edge is taken on every character except these ones. The complement of a pattern accepts
+    // characters the pattern never mentions, so there'd be no other way to write its edges down.
+    excluded: Option<Arc<BTreeSet<char>>>,
 }
 
+#[derive(Clone, Copy)]
 pub struct AutomataComponent {
     start_state: NodeIndex,
     accept_state: NodeIndex,
@@ -15,7 +27,17 @@ pub struct AutomataComponent {
 
 impl AutomataState {
     pub fn new(accepting: bool) -> AutomataState {
-        return AutomataState { accepting };
+        return AutomataState {
+            accepting,
+            patterns: BTreeSet::new(),
+        };
+    }
+
+    pub fn with_patterns(accepting: bool, patterns: BTreeSet<PatternID>) -> AutomataState {
+        return AutomataState {
+            accepting,
+            patterns,
+        };
     }
 
     pub fn is_accepting(&self) -> bool {
@@ -25,20 +47,52 @@ impl AutomataState {
     pub fn mark_as_accepting(&mut self) {
         self.accepting = true;
     }
+
+    pub fn mark_as_accepting_pattern(&mut self, pattern: PatternID) {
+        self.accepting = true;
+        self.patterns.insert(pattern);
+    }
+
+    pub fn get_patterns(&self) -> &BTreeSet<PatternID> {
+        return &self.patterns;
+    }
 }
 
 impl AutomataLabel {
     pub fn new(label: Option<char>) -> AutomataLabel {
-        return AutomataLabel { label };
+        return AutomataLabel {
+            label,
+            excluded: None,
+        };
+    }
+
+    pub fn any_except(excluded: Arc<BTreeSet<char>>) -> AutomataLabel {
+        return AutomataLabel {
+            label: None,
+            excluded: Some(excluded),
+        };
     }
 
     pub fn is_empty(&self) -> bool {
-        return self.label == None;
+        return self.label.is_none() && self.excluded.is_none();
     }
 
+    // The character the edge is taken on, None for empty edges and ones taken on any character but some
     pub fn get_label(&self) -> Option<char> {
         return self.label;
     }
+
+    pub fn get_excluded(&self) -> Option<&BTreeSet<char>> {
+        return self.excluded.as_deref();
+    }
+
+    pub fn matches(&self, c: char) -> bool {
+        return match (self.label, &self.excluded) {
+            (Some(label), _) => label == c,
+            (None, Some(excluded)) => !excluded.contains(&c),
+            (None, None) => false,
+        };
+    }
 }
 
 impl AutomataComponent {
@@ -0,0 +1,490 @@
+/* Nothing about a pattern bounds how big its automata get. The NFA grows linearly with the pattern, which is
+ * still a lot for a pattern file with thousands of lines in it, and the DFA can be exponential in the size of
+ * the NFA. RegexBuilder puts a limit on each of them, so a hostile pattern gets an error rather than eating all
+ * the memory there is.
+ *
+ * The NFA is checked before it's built, since how many nodes it needs can be worked out from the postfix regex.
+ * The lazy DFAs treat hitting a limit the same as running out of cache, so a Regex or RegexSet just falls back to
+ * the Pike VM, whose memory is bounded by the size of the NFA. Anything that has to build every DFA state up front
+ * (DenseDfa, --dump-dfa, and the DFAs & and ~ are compiled through) can't fall back though, so there the limits
+ * are an error.
+ */
+
+use std::{collections::VecDeque, error::Error, fmt};
+
+use crate::{
+    lazy_dfa::DEFAULT_CACHE_CAPACITY,
+    matcher::Regex,
+    nfa::{count_nfa_nodes, NfaConstruction},
+    postfixer,
+    regex::RegexSymbol,
+    regex_set::RegexSet,
+};
+
+pub const DEFAULT_NFA_SIZE_LIMIT: usize = 1_000_000;
+pub const DEFAULT_NFA_EDGE_LIMIT: usize = 5_000_000;
+pub const DEFAULT_DFA_STATE_LIMIT: usize = 100_000;
+pub const DEFAULT_DFA_TRANSITION_LIMIT: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    // The query isn't a valid regex, with the message from the postfixer
+    Syntax(String),
+    NfaTooBig { limit: usize },
+    TooManyNfaEdges { limit: usize },
+    TooManyDfaStates { limit: usize },
+    TooManyDfaTransitions { limit: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            BuildError::Syntax(message) => write!(f, "{}", message),
+            BuildError::NfaTooBig { limit } => {
+                write!(f, "Error - The NFA needs more than {} nodes", limit)
+            }
+            BuildError::TooManyNfaEdges { limit } => {
+                write!(f, "Error - The NFA needs more than {} edges", limit)
+            }
+            BuildError::TooManyDfaStates { limit } => {
+                write!(f, "Error - The DFA needs more than {} states", limit)
+            }
+            BuildError::TooManyDfaTransitions { limit } => {
+                write!(f, "Error - The DFA needs more than {} transitions", limit)
+            }
+        };
+    }
+}
+
+impl Error for BuildError {}
+
+impl From<String> for BuildError {
+    fn from(message: String) -> BuildError {
+        return BuildError::Syntax(message);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    pub nfa_nodes: usize,
+    // Thompson's construction only adds a few edges per node, but Glushkov's can add one between every pair of
+    // characters, so its edges are limited too
+    pub nfa_edges: usize,
+    pub dfa_states: usize,
+    pub dfa_transitions: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> SizeLimits {
+        return SizeLimits {
+            nfa_nodes: DEFAULT_NFA_SIZE_LIMIT,
+            nfa_edges: DEFAULT_NFA_EDGE_LIMIT,
+            dfa_states: DEFAULT_DFA_STATE_LIMIT,
+            dfa_transitions: DEFAULT_DFA_TRANSITION_LIMIT,
+        };
+    }
+}
+
+impl SizeLimits {
+    // No limits at all, for when the automata are known to be small
+    pub fn unlimited() -> SizeLimits {
+        return SizeLimits {
+            nfa_nodes: usize::MAX,
+            nfa_edges: usize::MAX,
+            dfa_states: usize::MAX,
+            dfa_transitions: usize::MAX,
+        };
+    }
+
+    // Checks the NFA nfa::build_multi_nfa would build for the patterns fits, before building it
+    pub fn check_nfa(&self, postfix_regexes: &[VecDeque<RegexSymbol>]) -> Result<(), BuildError> {
+        if count_nfa_nodes(postfix_regexes) > self.nfa_nodes {
+            return Err(BuildError::NfaTooBig {
+                limit: self.nfa_nodes,
+            });
+        }
+
+        return Ok(());
+    }
+}
+
+pub struct RegexBuilder {
+    ignore_case: bool,
+    cache_capacity: usize,
+    limits: SizeLimits,
+    construction: NfaConstruction,
+}
+
+impl Default for RegexBuilder {
+    fn default() -> RegexBuilder {
+        return RegexBuilder::new();
+    }
+}
+
+impl RegexBuilder {
+    pub fn new() -> RegexBuilder {
+        return RegexBuilder {
+            ignore_case: false,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            limits: SizeLimits::default(),
+            construction: NfaConstruction::default(),
+        };
+    }
+
+    pub fn ignore_case(&mut self, ignore_case: bool) -> &mut RegexBuilder {
+        self.ignore_case = ignore_case;
+        return self;
+    }
+
+    // The most memory in bytes each lazy DFA may use for its cache
+    pub fn cache_capacity(&mut self, cache_capacity: usize) -> &mut RegexBuilder {
+        self.cache_capacity = cache_capacity;
+        return self;
+    }
+
+    pub fn nfa_size_limit(&mut self, nodes: usize) -> &mut RegexBuilder {
+        self.limits.nfa_nodes = nodes;
+        return self;
+    }
+
+    pub fn nfa_edge_limit(&mut self, edges: usize) -> &mut RegexBuilder {
+        self.limits.nfa_edges = edges;
+        return self;
+    }
+
+    pub fn dfa_state_limit(&mut self, states: usize) -> &mut RegexBuilder {
+        self.limits.dfa_states = states;
+        return self;
+    }
+
+    pub fn dfa_transition_limit(&mut self, transitions: usize) -> &mut RegexBuilder {
+        self.limits.dfa_transitions = transitions;
+        return self;
+    }
+
+    // Thompson's or Glushkov's construction for the NFA, or by default whichever suits each pattern best
+    pub fn nfa_construction(&mut self, construction: NfaConstruction) -> &mut RegexBuilder {
+        self.construction = construction;
+        return self;
+    }
+
+    pub fn get_size_limits(&self) -> SizeLimits {
+        return self.limits;
+    }
+
+    pub fn build(&self, query: &str) -> Result<Regex, BuildError> {
+        let postfix_regex = postfixer::transform(query.to_string())?;
+
+        return Regex::from_postfix(
+            postfix_regex,
+            self.ignore_case,
+            self.cache_capacity,
+            self.limits,
+            self.construction,
+        );
+    }
+
+    // Matches wherever any of the queries would, as if they'd all been joined up into one alternation.
+    // With no queries at all it never matches.
+    pub fn build_many<S: AsRef<str>>(&self, queries: &[S]) -> Result<Regex, BuildError> {
+        if queries.is_empty() {
+            return self.build_fixed_strings::<&str>(&[]);
+        }
+
+        let mut combined: VecDeque<RegexSymbol> = VecDeque::new();
+        let mut has_empty = false;
+
+        for query in queries {
+            let postfix_regex = postfixer::transform(query.as_ref().to_string())?;
+
+            // There's no symbol for the empty pattern, so it gets joined up below instead
+            if postfix_regex.is_empty() {
+                has_empty = true;
+                continue;
+            }
+
+            let is_first = combined.is_empty();
+            combined.extend(postfix_regex);
+
+            if !is_first {
+                combined.push_back(RegexSymbol::Alternation);
+            }
+        }
+
+        // Alternating with the empty pattern is the same as making the rest optional
+        if has_empty && !combined.is_empty() {
+            combined.push_back(RegexSymbol::Optional);
+        }
+
+        return Regex::from_postfix(
+            combined,
+            self.ignore_case,
+            self.cache_capacity,
+            self.limits,
+            self.construction,
+        );
+    }
+
+    // Matches any of the literals exactly as written, see Regex::fixed_strings
+    pub fn build_fixed_strings<S: AsRef<str>>(&self, literals: &[S]) -> Result<Regex, BuildError> {
+        let literals = literals.iter().map(|l| l.as_ref().to_string()).collect();
+
+        return Regex::from_literals(
+            literals,
+            self.ignore_case,
+            self.cache_capacity,
+            self.limits,
+            self.construction,
+        );
+    }
+
+    pub fn build_set<S: AsRef<str>>(&self, queries: &[S]) -> Result<RegexSet, BuildError> {
+        let mut postfix_regexes: Vec<VecDeque<RegexSymbol>> = Vec::new();
+
+        for query in queries {
+            postfix_regexes.push(postfixer::transform(query.as_ref().to_string())?);
+        }
+
+        let patterns = queries.iter().map(|q| q.as_ref().to_string()).collect();
+
+        return RegexSet::from_postfix(
+            patterns,
+            postfix_regexes,
+            self.ignore_case,
+            self.cache_capacity,
+            self.limits,
+            self.construction,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_pattern_whose_nfa_is_too_big_when_building_should_return_a_typed_error() {
+        let query = "abcdefghij".repeat(10);
+        let result = RegexBuilder::new().nfa_size_limit(100).build(&query);
+
+        assert_eq!(result.err(), Some(BuildError::NfaTooBig { limit: 100 }));
+
+        assert!(RegexBuilder::new()
+            .nfa_size_limit(200)
+            .build(&query)
+            .is_ok());
+    }
+
+    #[test]
+    fn given_glushkov_pattern_with_too_many_edges_when_building_should_error_unless_it_can_fall_back(
+    ) {
+        // Every word can follow every other one, so Glushkov's construction needs 200 * 200 edges between them
+        let words: Vec<String> = (0..200).map(|i| format!("w{}", i)).collect();
+        let query = format!("({})*x", words.join("|"));
+        let mut builder = RegexBuilder::new();
+
+        builder.nfa_edge_limit(10_000);
+
+        let result = builder
+            .nfa_construction(NfaConstruction::Glushkov)
+            .build(&query);
+        assert_eq!(
+            result.err(),
+            Some(BuildError::TooManyNfaEdges { limit: 10_000 })
+        );
+
+        // Thompson's construction only needs a few edges per character
+        let regex = builder
+            .nfa_construction(NfaConstruction::Auto)
+            .build(&query)
+            .unwrap();
+        assert!(regex.is_match("w7w199x"));
+
+        builder.nfa_edge_limit(50_000);
+        assert!(builder
+            .nfa_construction(NfaConstruction::Glushkov)
+            .build(&query)
+            .is_ok());
+    }
+
+    #[test]
+    fn given_fixed_strings_too_big_for_the_nfa_limit_when_building_should_return_a_typed_error() {
+        let literal = "x".repeat(100);
+        let mut builder = RegexBuilder::new();
+
+        builder.nfa_size_limit(150);
+
+        assert_eq!(
+            builder.build_fixed_strings(&[&literal]).err(),
+            Some(BuildError::NfaTooBig { limit: 150 })
+        );
+        assert_eq!(
+            builder.build_fixed_strings(&["ab"; 40]).err(),
+            Some(BuildError::NfaTooBig { limit: 150 })
+        );
+        assert!(builder.build_fixed_strings(&["ab"; 30]).is_ok());
+    }
+
+    #[test]
+    fn given_patterns_whose_combined_nfa_is_too_big_when_building_should_count_all_of_them() {
+        let queries = ["a+b", "(c|d)*", "e?"];
+
+        // 6 nodes for the first, 8 for the second, 4 for the third and 2 joining them up
+        let result = RegexBuilder::new().nfa_size_limit(19).build_set(&queries);
+        assert_eq!(result.err(), Some(BuildError::NfaTooBig { limit: 19 }));
+
+        assert!(RegexBuilder::new()
+            .nfa_size_limit(20)
+            .build_set(&queries)
+            .is_ok());
+    }
+
+    #[test]
+    fn given_dfa_limits_when_matching_should_fall_back_to_the_pike_vm_rather_than_fail() {
+        let query = format!("(a|b)*a{}c", "(a|b)".repeat(6));
+        // Every 7 bit number written out in a's and b's, so the DFA has to visit loads of distinct states
+        let haystack: String = (0..128)
+            .map(|i: u32| format!("{:07b}", i).replace('0', "a").replace('1', "b"))
+            .collect();
+
+        let regex = RegexBuilder::new()
+            .dfa_state_limit(16)
+            .build(&query)
+            .unwrap();
+
+        // Leading c so the prefilter doesn't reject the line before the DFA gets to see it
+        assert!(!regex.is_match(&format!("c{}", haystack)));
+        assert!(!regex.is_using_dfa());
+        assert!(regex.is_match(&format!("{}abbbbbbc", haystack)));
+
+        let regex = RegexBuilder::new()
+            .dfa_transition_limit(16)
+            .build(&query)
+            .unwrap();
+
+        assert!(!regex.is_match(&format!("c{}", haystack)));
+        assert!(!regex.is_using_dfa());
+    }
+
+    #[test]
+    fn given_complement_of_exponential_pattern_when_building_should_hit_the_dfa_limit_not_hang() {
+        // The DFA for the operand needs a state for every combination of the last 13 characters
+        let query = format!("~((a|b)*a{})", "(a|b)".repeat(12));
+
+        for query in [query.clone(), format!("(a|b)+&{}", query)] {
+            let result = RegexBuilder::new().dfa_state_limit(1000).build(&query);
+
+            assert_eq!(
+                result.err(),
+                Some(BuildError::TooManyDfaStates { limit: 1000 }),
+                "{}",
+                query
+            );
+        }
+
+        assert!(RegexBuilder::new()
+            .dfa_state_limit(10_000)
+            .build(&query)
+            .is_ok());
+    }
+
+    #[test]
+    fn given_each_nfa_construction_when_matching_should_find_the_same_matches() {
+        let haystack = "xabb abab bbabbx aabba";
+
+        for query in ["(a|b)*abb", "b(a|b)?a", "((a|b)+&~(ab))x", "x?"] {
+            let find_all = |construction| {
+                let regex = RegexBuilder::new()
+                    .nfa_construction(construction)
+                    .build(query)
+                    .unwrap();
+
+                return regex
+                    .find_iter(haystack)
+                    .map(|m| (m.get_start(), m.get_end()))
+                    .collect::<Vec<(usize, usize)>>();
+            };
+
+            let thompson = find_all(NfaConstruction::Thompson);
+
+            assert_eq!(find_all(NfaConstruction::Glushkov), thompson, "{}", query);
+            assert_eq!(find_all(NfaConstruction::Auto), thompson, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_patterns_when_choosing_nfa_construction_should_only_pick_glushkov_where_it_can_be_used(
+    ) {
+        let choose = |construction: NfaConstruction, query: &str| {
+            return construction.choose(&postfixer::transform(query.to_string()).unwrap(), false);
+        };
+
+        assert_eq!(
+            choose(NfaConstruction::Auto, "(a|b)*abb"),
+            NfaConstruction::Glushkov
+        );
+        assert_eq!(
+            choose(NfaConstruction::Thompson, "(a|b)*abb"),
+            NfaConstruction::Thompson
+        );
+        assert_eq!(
+            choose(NfaConstruction::Glushkov, "a&b"),
+            NfaConstruction::Thompson
+        );
+
+        // Every word can follow every other one, so there'd be an edge between every pair of them
+        let words: Vec<String> = (0..1500).map(|i| format!("w{}", i)).collect();
+        let query = format!("({})*", words.join("|"));
+
+        assert_eq!(
+            choose(NfaConstruction::Auto, &query),
+            NfaConstruction::Thompson
+        );
+
+        // Ignoring case doubles the edges into each w, which takes 800 words over the limit
+        let words: Vec<String> = (0..800).map(|i| format!("w{}", i)).collect();
+        let postfix_regex = postfixer::transform(format!("({})*", words.join("|"))).unwrap();
+
+        assert_eq!(
+            NfaConstruction::Auto.choose(&postfix_regex, false),
+            NfaConstruction::Glushkov
+        );
+        assert_eq!(
+            NfaConstruction::Auto.choose(&postfix_regex, true),
+            NfaConstruction::Thompson
+        );
+    }
+
+    #[test]
+    fn given_empty_pattern_when_building_should_match_every_line_like_the_empty_fixed_string() {
+        let lines = ["", "foo", "bar"];
+
+        for regex in [
+            RegexBuilder::new().build("").unwrap(),
+            RegexBuilder::new().build_many(&["", "foo"]).unwrap(),
+            RegexBuilder::new().build_many(&["foo", ""]).unwrap(),
+            Regex::fixed_string("", false).unwrap(),
+        ] {
+            assert!(lines.iter().all(|line| regex.is_match(line)));
+        }
+
+        // Still leftmost-longest, so the empty pattern doesn't hide a longer match
+        let regex = RegexBuilder::new().build_many(&["", "foo"]).unwrap();
+        let found: Vec<(usize, usize)> = regex
+            .find_iter("foo")
+            .map(|m| (m.get_start(), m.get_end()))
+            .collect();
+
+        assert_eq!(found, vec![(0, 3)]);
+    }
+
+    #[test]
+    fn given_invalid_query_when_building_should_return_a_syntax_error() {
+        let result = RegexBuilder::new().build("(a");
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Error - Unbalanced brackets"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeSet;
+
+/* Pairs of characters that are equivalent under Unicode simple case folding, but which can't be found
+ * from one another by just taking the single character upper and lower case mappings in std.
+ * e.g. the Kelvin sign lowercases to 'k', but nothing uppercases to the Kelvin sign.
+ *
+ * Link: https://www.unicode.org/Public/UCD/latest/ucd/CaseFolding.txt
+ */
+const EXTRA_EQUIVALENCES: [(char, char); 27] = [
+    ('k', '\u{212A}'),        // KELVIN SIGN
+    ('s', '\u{017F}'),        // LATIN SMALL LETTER LONG S
+    ('\u{00E5}', '\u{212B}'), // ANGSTROM SIGN
+    ('\u{00DF}', '\u{1E9E}'), // LATIN CAPITAL LETTER SHARP S
+    ('\u{00B5}', '\u{03BC}'), // MICRO SIGN
+    ('\u{03B2}', '\u{03D0}'), // GREEK BETA SYMBOL
+    ('\u{03B5}', '\u{03F5}'), // GREEK LUNATE EPSILON SYMBOL
+    ('\u{03B8}', '\u{03D1}'), // GREEK THETA SYMBOL
+    ('\u{03B8}', '\u{03F4}'), // GREEK CAPITAL THETA SYMBOL
+    ('\u{03B9}', '\u{0345}'), // COMBINING GREEK YPOGEGRAMMENI
+    ('\u{03B9}', '\u{1FBE}'), // GREEK PROSGEGRAMMENI
+    ('\u{03BA}', '\u{03F0}'), // GREEK KAPPA SYMBOL
+    ('\u{03C0}', '\u{03D6}'), // GREEK PI SYMBOL
+    ('\u{03C1}', '\u{03F1}'), // GREEK RHO SYMBOL
+    ('\u{03C3}', '\u{03C2}'), // GREEK SMALL LETTER FINAL SIGMA
+    ('\u{03C6}', '\u{03D5}'), // GREEK PHI SYMBOL
+    ('\u{03C9}', '\u{2126}'), // OHM SIGN
+    ('\u{1E61}', '\u{1E9B}'), // LATIN SMALL LETTER LONG S WITH DOT ABOVE
+    ('\u{0432}', '\u{1C80}'), // CYRILLIC SMALL LETTER ROUNDED VE
+    ('\u{0434}', '\u{1C81}'), // CYRILLIC SMALL LETTER LONG-LEGGED DE
+    ('\u{043E}', '\u{1C82}'), // CYRILLIC SMALL LETTER NARROW O
+    ('\u{0441}', '\u{1C83}'), // CYRILLIC SMALL LETTER WIDE ES
+    ('\u{0442}', '\u{1C84}'), // CYRILLIC SMALL LETTER TALL TE
+    ('\u{0442}', '\u{1C85}'), // CYRILLIC SMALL LETTER THREE-LEGGED TE
+    ('\u{044A}', '\u{1C86}'), // CYRILLIC SMALL LETTER TALL HARD SIGN
+    ('\u{0463}', '\u{1C87}'), // CYRILLIC SMALL LETTER TALL YAT
+    ('\u{A64B}', '\u{1C88}'), // CYRILLIC SMALL LETTER UNBLENDED UK
+];
+
+// Returns every character that's equivalent to c when ignoring case (including c itself) in sorted order.
+// Only single character mappings count, so e.g. 'ß' doesn't expand to "ss".
+pub fn simple_case_fold_set(c: char) -> Vec<char> {
+    let mut result: BTreeSet<char> = BTreeSet::new();
+    let mut visit_stack: Vec<char> = vec![c];
+
+    while let Some(current) = visit_stack.pop() {
+        if !result.insert(current) {
+            continue;
+        }
+
+        if let Some(lower) = single_char(current.to_lowercase()) {
+            visit_stack.push(lower);
+        }
+
+        if let Some(upper) = single_char(current.to_uppercase()) {
+            visit_stack.push(upper);
+        }
+
+        for (left, right) in EXTRA_EQUIVALENCES {
+            if current == left {
+                visit_stack.push(right);
+            } else if current == right {
+                visit_stack.push(left);
+            }
+        }
+    }
+
+    return result.into_iter().collect();
+}
+
+// Picks one character to stand for c's whole fold set, so two characters are equal ignoring case exactly
+// when their canonical folds are equal
+pub fn canonical_fold(c: char) -> char {
+    // Nothing outside ASCII sorts before an ASCII letter, so its upper case is always the smallest in its set
+    if c.is_ascii() {
+        return c.to_ascii_uppercase();
+    }
+
+    return simple_case_fold_set(c)[0];
+}
+
+fn single_char(mut mapping: impl Iterator<Item = char>) -> Option<char> {
+    let first = mapping.next();
+
+    return match mapping.next() {
+        Some(_) => None,
+        None => first,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_ascii_letters_when_folding_should_return_both_cases() {
+        assert_eq!(simple_case_fold_set('a'), vec!['A', 'a']);
+        assert_eq!(simple_case_fold_set('Q'), vec!['Q', 'q']);
+    }
+
+    #[test]
+    fn given_characters_without_case_when_folding_should_return_just_themselves() {
+        assert_eq!(simple_case_fold_set('1'), vec!['1']);
+        assert_eq!(simple_case_fold_set('!'), vec!['!']);
+    }
+
+    #[test]
+    fn given_characters_with_non_round_trip_foldings_when_folding_should_return_the_whole_equivalence_class(
+    ) {
+        assert_eq!(simple_case_fold_set('k'), vec!['K', 'k', '\u{212A}']);
+        assert_eq!(simple_case_fold_set('\u{212A}'), vec!['K', 'k', '\u{212A}']);
+        assert_eq!(simple_case_fold_set('ς'), vec!['Σ', 'ς', 'σ']);
+        assert_eq!(simple_case_fold_set('ß'), vec!['ß', 'ẞ']);
+        assert_eq!(simple_case_fold_set('д'), vec!['Д', 'д', '\u{1C81}']);
+        assert_eq!(
+            simple_case_fold_set('т'),
+            vec!['Т', 'т', '\u{1C84}', '\u{1C85}']
+        );
+    }
+
+    #[test]
+    fn given_equivalent_characters_when_canonically_folding_should_pick_the_same_character() {
+        assert_eq!(canonical_fold('k'), canonical_fold('\u{212A}'));
+        assert_eq!(canonical_fold('K'), 'K');
+        assert_eq!(canonical_fold('ς'), canonical_fold('Σ'));
+        assert_ne!(canonical_fold('a'), canonical_fold('b'));
+    }
+}
@@ -0,0 +1,168 @@
+/* Plenty of characters in a pattern's alphabet behave exactly the same as some other character, e.g. with case
+ * folding k, K and the Kelvin sign always go along the same edges. Working out every transition for each of them
+ * separately is wasted work, and gives a DFA table a column for each.
+ *
+ * So before determinizing, the alphabet is split up into classes of characters that can never be told apart by
+ * the NFA: two characters are in the same class if they're on exactly the same edges. Then only one character
+ * of each class (its representative) needs stepping through the NFA, and everything else in its class goes to
+ * the same state.
+ *
+ * Class 0 is every character outside the alphabet, which all go along the same edges as the character
+ * nfa::get_other_char picks.
+ *
+ * The same idea as the byte classes other regex engines use, except our edges are labelled with single characters
+ * rather than byte ranges, so the classes are worked out from the edges themselves.
+ */
+
+use std::collections::HashMap;
+
+use crate::{
+    automata::{AutomataLabel, AutomataState},
+    graph::{Graph, NodeIndex},
+    nfa::get_other_char,
+};
+
+pub type ClassId = usize;
+
+#[derive(Clone)]
+pub struct CharClasses {
+    // The class of every character in the alphabet, anything else is in class 0
+    ascii: [ClassId; 128],
+    non_ascii: HashMap<char, ClassId>,
+    // The characters in each class, in the order they were given, with the class 0 character first in its class
+    members: Vec<Vec<char>>,
+}
+
+impl CharClasses {
+    // Splits the alphabet up into classes, where the alphabet has every character the NFA can take outside of
+    // class 0 (see nfa::get_nfa_alphabet)
+    pub fn new(nfa: &Graph<AutomataState, AutomataLabel>, alphabet: &[char]) -> CharClasses {
+        let mut chars: Vec<char> = vec![get_other_char(alphabet)];
+        let mut index: HashMap<char, usize> = HashMap::new();
+
+        for c in alphabet {
+            if !index.contains_key(c) && *c != chars[0] {
+                index.insert(*c, chars.len());
+                chars.push(*c);
+            }
+        }
+
+        // The edges each character goes along, as (from, to) pairs. They're found in node order, so the same
+        // edges always come out in the same order.
+        let mut edges: Vec<Vec<(NodeIndex, NodeIndex)>> = vec![Vec::new(); chars.len()];
+
+        for node in 0..nfa.num_of_nodes() {
+            for edge in nfa.outgoing_edges(node).unwrap() {
+                let label = nfa.get_edge_data(&edge).unwrap().clone();
+                let target = nfa.traverse(edge).unwrap();
+
+                if label.is_empty() {
+                    continue;
+                }
+
+                match label.get_label() {
+                    Some(c) => {
+                        if let Some(i) = index.get(&c) {
+                            edges[*i].push((node, target));
+                        }
+                    }
+                    None => {
+                        for (i, c) in chars.iter().enumerate() {
+                            if label.matches(*c) {
+                                edges[i].push((node, target));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut classes: HashMap<Vec<(NodeIndex, NodeIndex)>, ClassId> = HashMap::new();
+        let mut members: Vec<Vec<char>> = Vec::new();
+        let mut ascii = [0; 128];
+        let mut non_ascii: HashMap<char, ClassId> = HashMap::new();
+
+        // The class 0 character comes first, so it's always the one to start class 0
+        for (c, signature) in chars.into_iter().zip(edges) {
+            let class = *classes.entry(signature).or_insert(members.len());
+
+            if class == members.len() {
+                members.push(Vec::new());
+            }
+
+            members[class].push(c);
+
+            if c.is_ascii() {
+                ascii[c as usize] = class;
+            } else {
+                non_ascii.insert(c, class);
+            }
+        }
+
+        return CharClasses {
+            ascii,
+            non_ascii,
+            members,
+        };
+    }
+
+    pub fn get_class(&self, c: char) -> ClassId {
+        if c.is_ascii() {
+            return self.ascii[c as usize];
+        }
+
+        return *self.non_ascii.get(&c).unwrap_or(&0);
+    }
+
+    pub fn num_of_classes(&self) -> usize {
+        return self.members.len();
+    }
+
+    // One character from each class, in class order, to step through the NFA for the whole class
+    pub fn representatives(&self) -> Vec<char> {
+        return self.members.iter().map(|members| members[0]).collect();
+    }
+
+    pub fn get_members(&self, class: ClassId) -> &[char] {
+        return &self.members[class];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::SizeLimits,
+        nfa::{build_nfa, get_nfa_alphabet},
+        postfixer,
+    };
+
+    fn classes(query: &str, ignore_case: bool) -> CharClasses {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (_, nfa) = build_nfa(postfix_regex, ignore_case, SizeLimits::unlimited()).unwrap();
+
+        return CharClasses::new(&nfa, &get_nfa_alphabet(&nfa));
+    }
+
+    #[test]
+    fn given_ignore_case_when_splitting_alphabet_should_put_every_case_of_a_char_in_one_class() {
+        let classes = classes("k(a|b)", true);
+
+        // Everything else, then k, a and b with their other cases
+        assert_eq!(classes.num_of_classes(), 4);
+        assert_eq!(classes.get_class('k'), classes.get_class('K'));
+        assert_eq!(classes.get_class('k'), classes.get_class('\u{212A}'));
+        assert_eq!(classes.get_class('a'), classes.get_class('A'));
+        assert_ne!(classes.get_class('a'), classes.get_class('b'));
+    }
+
+    #[test]
+    fn given_chars_outside_alphabet_when_getting_class_should_be_class_0() {
+        let classes = classes("ab|é", false);
+
+        assert_eq!(classes.get_class('z'), 0);
+        assert_eq!(classes.get_class('ü'), 0);
+        assert_ne!(classes.get_class('é'), 0);
+        assert_eq!(classes.get_members(0).len(), 1);
+    }
+}
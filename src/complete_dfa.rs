@@ -0,0 +1,916 @@
+/* A DFA with a transition out of every state on every character, built from the DFA dfa::build_dfa gives.
+ * The DFA from build_dfa leaves out transitions that can't lead to a match, so completing it means adding a
+ * dead state for all of them to go to, along with one transition from each state for all the characters
+ * outside the alphabet.
+ *
+ * Once complete the language operations are simple:
+ *
+ * complement       swap which states are accepting, since every string ends up in exactly one state
+ * intersection     run both DFAs side by side (the product construction), accepting where both do
+ * union            the same, but accepting where either does
+ * difference       the same, but accepting where the first does and the second doesn't
+ *
+ * These are what the & and ~ operators compile to, e.g. (a|b)+&~(ab) is every string of a's and b's except ab.
+ * Each operand gets turned into a CompleteDfa, combined, and then put back into the NFA as a component.
+ *
+ * Every string ends up in exactly one state, so questions about the language as a whole (is anything matched,
+ * are there only finitely many matches, how long are they, how many are there) come down to following paths
+ * through the transitions.
+ *
+ * Link: https://en.wikipedia.org/wiki/Induction_of_regular_languages#Product_construction
+ */
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    automata::{AutomataComponent, AutomataLabel, AutomataState},
+    builder::{BuildError, SizeLimits},
+    dfa::build_dfa_with_limits,
+    graph::Graph,
+    nfa::{build_nfa, get_nfa_alphabet, get_other_char},
+    postfixer,
+    state_elimination::{eliminate, Expr},
+};
+
+type StateId = usize;
+
+// Every char there is, which is every code point apart from the surrogates
+const NUM_OF_CHARS: usize = 0x110000 - 0x800;
+
+#[derive(Clone)]
+pub struct CompleteDfa {
+    // Sorted, every character outside it goes along the same transitions
+    alphabet: Vec<char>,
+    start: StateId,
+    // Column 0 is for characters outside the alphabet, and column i + 1 for alphabet[i]
+    transitions: Vec<Vec<StateId>>,
+    accepting: Vec<bool>,
+}
+
+impl CompleteDfa {
+    pub fn new(query: &str, ignore_case: bool) -> Result<CompleteDfa, BuildError> {
+        return CompleteDfa::with_limits(query, ignore_case, SizeLimits::default());
+    }
+
+    // Every state gets built up front, so going over a limit is an error rather than something to fall back from
+    pub fn with_limits(
+        query: &str,
+        ignore_case: bool,
+        limits: SizeLimits,
+    ) -> Result<CompleteDfa, BuildError> {
+        let postfix_regex = postfixer::transform(query.to_string())?;
+
+        // The empty pattern only matches the empty string
+        if postfix_regex.is_empty() {
+            return Ok(CompleteDfa {
+                alphabet: Vec::new(),
+                start: 0,
+                transitions: vec![vec![1], vec![1]],
+                accepting: vec![true, false],
+            });
+        }
+
+        limits.check_nfa(std::slice::from_ref(&postfix_regex))?;
+
+        let (handle, nfa) = build_nfa(postfix_regex, ignore_case, limits)?;
+
+        return CompleteDfa::from_nfa(&handle, &nfa, limits);
+    }
+
+    // Determinizes the part of the NFA between the start and accept states of the component
+    pub fn from_nfa(
+        handle: &AutomataComponent,
+        nfa: &Graph<AutomataState, AutomataLabel>,
+        limits: SizeLimits,
+    ) -> Result<CompleteDfa, BuildError> {
+        let alphabet = get_nfa_alphabet(nfa);
+        let other = get_other_char(&alphabet);
+
+        let mut extended = alphabet.clone();
+        extended.push(other);
+
+        let (start, dfa) = build_dfa_with_limits(*handle, nfa, extended, limits)?;
+
+        let dead = dfa.num_of_nodes();
+        let mut transitions = vec![vec![dead; alphabet.len() + 1]; dead + 1];
+        let mut accepting = vec![false; dead + 1];
+
+        for (state, row) in transitions.iter_mut().enumerate().take(dead) {
+            accepting[state] = dfa.get_node_data(&state).unwrap().is_accepting();
+
+            for edge in dfa.outgoing_edges(state).unwrap() {
+                let c = *dfa.get_edge_data(&edge).unwrap();
+                let column = match alphabet.binary_search(&c) {
+                    Ok(i) => i + 1,
+                    Err(_) => 0,
+                };
+
+                row[column] = dfa.traverse(edge).unwrap();
+            }
+        }
+
+        return Ok(CompleteDfa {
+            alphabet,
+            start,
+            transitions,
+            accepting,
+        });
+    }
+
+    pub fn complement(&self) -> CompleteDfa {
+        return CompleteDfa {
+            accepting: self.accepting.iter().map(|a| !a).collect(),
+            ..self.clone()
+        };
+    }
+
+    pub fn intersection(&self, other: &CompleteDfa) -> CompleteDfa {
+        return self
+            .intersection_with_limits(other, SizeLimits::unlimited())
+            .unwrap();
+    }
+
+    // The product can have as many states as both DFAs multiplied together, so this gives up past the DFA limits
+    pub fn intersection_with_limits(
+        &self,
+        other: &CompleteDfa,
+        limits: SizeLimits,
+    ) -> Result<CompleteDfa, BuildError> {
+        return self.product(other, |a, b| a && b, limits);
+    }
+
+    pub fn union(&self, other: &CompleteDfa) -> CompleteDfa {
+        return self
+            .product(other, |a, b| a || b, SizeLimits::unlimited())
+            .unwrap();
+    }
+
+    pub fn difference(&self, other: &CompleteDfa) -> CompleteDfa {
+        return self
+            .product(other, |a, b| a && !b, SizeLimits::unlimited())
+            .unwrap();
+    }
+
+    // Whether the whole of the input is in the language, not just some part of it
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut state = self.start;
+
+        for c in input.chars() {
+            state = self.transitions[state][self.column(c)];
+        }
+
+        return self.accepting[state];
+    }
+
+    // Nothing at all is matched, so searching with the pattern can never find anything
+    pub fn matches_nothing(&self) -> bool {
+        return !self.live_states()[self.start];
+    }
+
+    // The empty string is matched, so searching with the pattern finds a match on every line
+    pub fn matches_empty_string(&self) -> bool {
+        return self.accepting[self.start];
+    }
+
+    // There are only so many strings matched, i.e. no cycle in the DFA is on the way to a match
+    pub fn is_finite(&self) -> bool {
+        return self.useful_states_in_order().is_some();
+    }
+
+    // The length in characters of the shortest string matched, or None if nothing is
+    pub fn min_match_len(&self) -> Option<usize> {
+        return self.shortest_match().map(|s| s.chars().count());
+    }
+
+    // The length in characters of the longest string matched, or None if nothing is or there's no longest one
+    pub fn max_match_len(&self) -> Option<usize> {
+        let order = self.useful_states_in_order()?;
+        let mut longest: Vec<Option<usize>> = vec![None; self.num_of_states()];
+        let mut max_len: Option<usize> = None;
+
+        longest[self.start] = Some(0);
+
+        // Every way into a state comes before it in the order, so its longest path is known by the time it's reached
+        for state in order {
+            let Some(len) = longest[state] else {
+                continue;
+            };
+
+            if self.accepting[state] {
+                max_len = max_len.max(Some(len));
+            }
+
+            for target in self.transitions[state].iter() {
+                longest[*target] = longest[*target].max(Some(len + 1));
+            }
+        }
+
+        return max_len;
+    }
+
+    // How many strings of exactly n characters are matched, or None if there are more than fit in a u128.
+    // Each character outside the alphabet is a different string, so a transition on column 0 counts for all of
+    // them, e.g. ~a has over a million strings of length 1.
+    pub fn count_matches_of_len(&self, n: usize) -> Option<u128> {
+        let weights: Vec<u128> = std::iter::once((NUM_OF_CHARS - self.alphabet.len()) as u128)
+            .chain(self.alphabet.iter().map(|_| 1))
+            .collect();
+
+        // How many strings of the length so far lead from the start state to each state
+        let mut ways: Vec<u128> = vec![0; self.num_of_states()];
+        ways[self.start] = 1;
+
+        for _ in 0..n {
+            let mut next: Vec<u128> = vec![0; self.num_of_states()];
+
+            for (state, count) in ways.iter().enumerate().filter(|(_, count)| **count > 0) {
+                for (column, target) in self.transitions[state].iter().enumerate() {
+                    let added = count.checked_mul(weights[column])?;
+                    next[*target] = next[*target].checked_add(added)?;
+                }
+            }
+
+            ways = next;
+        }
+
+        return (0..self.num_of_states())
+            .filter(|state| self.accepting[*state])
+            .try_fold(0u128, |total, state| total.checked_add(ways[state]));
+    }
+
+    // The shortest string in one of the languages but not the other, or None if they're the same language
+    pub fn find_difference(&self, other: &CompleteDfa) -> Option<String> {
+        return self
+            .find_difference_with_limits(other, SizeLimits::unlimited())
+            .unwrap();
+    }
+
+    pub fn find_difference_with_limits(
+        &self,
+        other: &CompleteDfa,
+        limits: SizeLimits,
+    ) -> Result<Option<String>, BuildError> {
+        return Ok(self.product(other, |a, b| a != b, limits)?.shortest_match());
+    }
+
+    // The shortest string in this language that isn't in the other, or None if this one is a subset of it
+    pub fn find_not_in(&self, other: &CompleteDfa) -> Option<String> {
+        return self
+            .find_not_in_with_limits(other, SizeLimits::unlimited())
+            .unwrap();
+    }
+
+    pub fn find_not_in_with_limits(
+        &self,
+        other: &CompleteDfa,
+        limits: SizeLimits,
+    ) -> Result<Option<String>, BuildError> {
+        return Ok(self
+            .product(other, |a, b| a && !b, limits)?
+            .shortest_match());
+    }
+
+    // Breadth first from the start state, trying characters in order, so the first accepting state found is
+    // reached by the shortest string and the first in alphabetical order out of the shortest ones
+    pub fn shortest_match(&self) -> Option<String> {
+        let mut parents: Vec<Option<(StateId, char)>> = vec![None; self.num_of_states()];
+        let mut visited = vec![false; self.num_of_states()];
+        let mut worklist: VecDeque<StateId> = VecDeque::from([self.start]);
+
+        visited[self.start] = true;
+
+        let chars = self.example_chars();
+
+        while let Some(state) = worklist.pop_front() {
+            if self.accepting[state] {
+                let mut path: Vec<char> = Vec::new();
+                let mut current = state;
+
+                while let Some((parent, c)) = parents[current] {
+                    path.push(c);
+                    current = parent;
+                }
+
+                return Some(path.into_iter().rev().collect());
+            }
+
+            for (c, column) in chars.iter() {
+                let next = self.transitions[state][*column];
+
+                if !visited[next] {
+                    visited[next] = true;
+                    parents[next] = Some((state, *c));
+                    worklist.push_back(next);
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // The smallest DFA for the same language, found by splitting the states into groups until every state in a
+    // group goes to the same groups as the rest of it, and then making each group a state (Moore's algorithm).
+    // The states are numbered in the order they're found going breadth first from the start, and characters that
+    // go the same way as the ones outside the alphabet are dropped from it, so any two patterns for the same
+    // language give exactly the same DFA.
+    // Link: https://en.wikipedia.org/wiki/DFA_minimization
+    pub fn minimize(&self) -> CompleteDfa {
+        let mut groups: Vec<usize> = self.accepting.iter().map(|a| *a as usize).collect();
+        let mut num_of_groups = 0;
+
+        loop {
+            let mut ids: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+
+            groups = (0..self.num_of_states())
+                .map(|state| {
+                    let targets = self.transitions[state].iter().map(|t| groups[*t]).collect();
+                    let id = ids.len();
+
+                    return *ids.entry((groups[state], targets)).or_insert(id);
+                })
+                .collect();
+
+            // Groups only ever get split, so if there are no more of them nothing changed
+            if ids.len() == num_of_groups {
+                break;
+            }
+
+            num_of_groups = ids.len();
+        }
+
+        let mut rows: Vec<Vec<usize>> = vec![Vec::new(); num_of_groups];
+        let mut accepting = vec![false; num_of_groups];
+
+        for state in 0..self.num_of_states() {
+            rows[groups[state]] = self.transitions[state].iter().map(|t| groups[*t]).collect();
+            accepting[groups[state]] = self.accepting[state];
+        }
+
+        let columns: Vec<usize> = std::iter::once(0)
+            .chain(
+                (1..=self.alphabet.len())
+                    .filter(|column| rows.iter().any(|row| row[*column] != row[0])),
+            )
+            .collect();
+
+        let mut state_ids: Vec<Option<StateId>> = vec![None; num_of_groups];
+        let mut order: Vec<usize> = vec![groups[self.start]];
+        let mut next = 0;
+
+        state_ids[groups[self.start]] = Some(0);
+
+        while next < order.len() {
+            for column in columns.iter() {
+                let target = rows[order[next]][*column];
+
+                if state_ids[target].is_none() {
+                    state_ids[target] = Some(order.len());
+                    order.push(target);
+                }
+            }
+
+            next += 1;
+        }
+
+        return CompleteDfa {
+            alphabet: columns[1..]
+                .iter()
+                .map(|column| self.alphabet[column - 1])
+                .collect(),
+            start: 0,
+            transitions: order
+                .iter()
+                .map(|group| {
+                    columns
+                        .iter()
+                        .map(|column| state_ids[rows[*group][*column]].unwrap())
+                        .collect()
+                })
+                .collect(),
+            accepting: order.iter().map(|group| accepting[*group]).collect(),
+        };
+    }
+
+    // A regex matching the same strings, see state_elimination. Characters outside the alphabet can only be written
+    // with ~, so when the complement's regex is shorter this gives ~ of that instead, e.g. ~(ab) rather than
+    // spelling out every string that isn't ab. The regex is a lot simpler if the DFA is minimized first.
+    pub fn to_regex(&self) -> String {
+        let direct = self.eliminate_states().to_string();
+        let complemented =
+            Expr::Complement(Box::new(self.complement().eliminate_states())).to_string();
+
+        if complemented.chars().count() < direct.chars().count() {
+            return complemented;
+        }
+
+        return direct;
+    }
+
+    fn eliminate_states(&self) -> Expr {
+        let excluded = Rc::new(self.alphabet.iter().copied().collect::<BTreeSet<char>>());
+        let mut edges: Vec<(StateId, StateId, Expr)> = Vec::new();
+
+        for (state, row) in self.transitions.iter().enumerate() {
+            for (column, target) in row.iter().enumerate() {
+                let label = match column {
+                    0 => Expr::NotIn(excluded.clone()),
+                    column => Expr::Char(self.alphabet[column - 1]),
+                };
+
+                edges.push((state, *target, label));
+            }
+        }
+
+        return eliminate(self.start, &self.accepting, edges);
+    }
+
+    pub fn num_of_states(&self) -> usize {
+        return self.transitions.len();
+    }
+
+    // Adds the DFA to the NFA as a component, with an empty edge from every accepting state to a new accept
+    // state. States that can't reach an accepting state can never lead to a match, so they're left out.
+    pub fn add_to_nfa(&self, nfa: &mut Graph<AutomataState, AutomataLabel>) -> AutomataComponent {
+        let live = self.live_states();
+        let mut nodes: HashMap<StateId, usize> = HashMap::new();
+
+        // The start state has to go in even if it's dead, the component needs somewhere to start from
+        nodes.insert(self.start, nfa.add_node(AutomataState::new(false)));
+
+        for (state, _) in live.iter().enumerate().filter(|(_, live)| **live) {
+            if state != self.start {
+                nodes.insert(state, nfa.add_node(AutomataState::new(false)));
+            }
+        }
+
+        let accept = nfa.add_node(AutomataState::new(false));
+        let excluded = Arc::new(self.alphabet.iter().copied().collect::<BTreeSet<char>>());
+
+        for state in (0..self.num_of_states()).filter(|s| live[*s]) {
+            let from = nodes[&state];
+
+            for (column, target) in self.transitions[state].iter().enumerate() {
+                if !live[*target] {
+                    continue;
+                }
+
+                let label = match column {
+                    0 => AutomataLabel::any_except(excluded.clone()),
+                    column => AutomataLabel::new(Some(self.alphabet[column - 1])),
+                };
+
+                nfa.add_edge(from, nodes[target], label);
+            }
+
+            if self.accepting[state] {
+                nfa.add_edge(from, accept, AutomataLabel::new(None));
+            }
+        }
+
+        return AutomataComponent::new(nodes[&self.start], accept);
+    }
+
+    pub(crate) fn get_start(&self) -> StateId {
+        return self.start;
+    }
+
+    pub(crate) fn is_accepting(&self, state: StateId) -> bool {
+        return self.accepting[state];
+    }
+
+    pub(crate) fn next_state(&self, state: StateId, column: usize) -> StateId {
+        return self.transitions[state][column];
+    }
+
+    // One character for each column along with the column, sorted by character. Column 0 gets a letter or digit
+    // outside the alphabet if there is one, so examples that use it stay readable.
+    pub(crate) fn example_chars(&self) -> Vec<(char, usize)> {
+        let other = ('a'..='z')
+            .chain('A'..='Z')
+            .chain('0'..='9')
+            .find(|c| self.alphabet.binary_search(c).is_err())
+            .unwrap_or_else(|| get_other_char(&self.alphabet));
+
+        let mut chars: Vec<(char, usize)> = std::iter::once((other, 0))
+            .chain(self.alphabet.iter().enumerate().map(|(i, c)| (*c, i + 1)))
+            .collect();
+        chars.sort();
+
+        return chars;
+    }
+
+    fn column(&self, c: char) -> usize {
+        return match self.alphabet.binary_search(&c) {
+            Ok(i) => i + 1,
+            Err(_) => 0,
+        };
+    }
+
+    // Runs both DFAs at once, with a state for every pair of their states reachable from the pair of start states
+    fn product(
+        &self,
+        other: &CompleteDfa,
+        accept: impl Fn(bool, bool) -> bool,
+        limits: SizeLimits,
+    ) -> Result<CompleteDfa, BuildError> {
+        let mut alphabet: Vec<char> = self.alphabet.clone();
+        alphabet.extend(other.alphabet.iter());
+        alphabet.sort();
+        alphabet.dedup();
+
+        // Column 0 stays column 0, since a character outside the combined alphabet is outside both of them
+        let columns = |dfa: &CompleteDfa| -> Vec<usize> {
+            return std::iter::once(0)
+                .chain(alphabet.iter().map(|c| dfa.column(*c)))
+                .collect();
+        };
+        let self_columns = columns(self);
+        let other_columns = columns(other);
+
+        let mut state_ids: HashMap<(StateId, StateId), StateId> = HashMap::new();
+        let mut pairs: Vec<(StateId, StateId)> = Vec::new();
+        let mut transitions: Vec<Vec<StateId>> = Vec::new();
+        let mut worklist: VecDeque<(StateId, StateId)> = VecDeque::new();
+
+        let start = (self.start, other.start);
+        state_ids.insert(start, 0);
+        pairs.push(start);
+        worklist.push_back(start);
+
+        while let Some((left, right)) = worklist.pop_front() {
+            let mut row: Vec<StateId> = Vec::new();
+
+            for column in 0..=alphabet.len() {
+                let next = (
+                    self.transitions[left][self_columns[column]],
+                    other.transitions[right][other_columns[column]],
+                );
+
+                let id = match state_ids.get(&next) {
+                    Some(id) => *id,
+                    None => {
+                        if pairs.len() == limits.dfa_states {
+                            return Err(BuildError::TooManyDfaStates {
+                                limit: limits.dfa_states,
+                            });
+                        }
+
+                        let id = pairs.len();
+
+                        state_ids.insert(next, id);
+                        pairs.push(next);
+                        worklist.push_back(next);
+
+                        id
+                    }
+                };
+
+                row.push(id);
+            }
+
+            transitions.push(row);
+
+            if transitions.len().saturating_mul(alphabet.len() + 1) > limits.dfa_transitions {
+                return Err(BuildError::TooManyDfaTransitions {
+                    limit: limits.dfa_transitions,
+                });
+            }
+        }
+
+        let accepting = pairs
+            .iter()
+            .map(|(left, right)| accept(self.accepting[*left], other.accepting[*right]))
+            .collect();
+
+        return Ok(CompleteDfa {
+            alphabet,
+            start: 0,
+            transitions,
+            accepting,
+        });
+    }
+
+    // The states on the way from the start state to a match, in topological order, or None if there's a cycle
+    // through them. Uses Kahn's algorithm, which only runs out of states with no way in left if there's a cycle.
+    // Link: https://en.wikipedia.org/wiki/Topological_sorting#Kahn's_algorithm
+    fn useful_states_in_order(&self) -> Option<Vec<StateId>> {
+        let live = self.live_states();
+        let mut reachable = vec![false; self.num_of_states()];
+        let mut stack: Vec<StateId> = vec![self.start];
+
+        reachable[self.start] = true;
+
+        while let Some(state) = stack.pop() {
+            for target in self.transitions[state].iter() {
+                if !reachable[*target] {
+                    reachable[*target] = true;
+                    stack.push(*target);
+                }
+            }
+        }
+
+        let useful: Vec<bool> = (0..self.num_of_states())
+            .map(|state| reachable[state] && live[state])
+            .collect();
+
+        let mut ways_in = vec![0; self.num_of_states()];
+
+        for state in (0..self.num_of_states()).filter(|s| useful[*s]) {
+            for target in self.transitions[state].iter().filter(|t| useful[**t]) {
+                ways_in[*target] += 1;
+            }
+        }
+
+        let mut order: Vec<StateId> = Vec::new();
+        let mut ready: Vec<StateId> = (0..self.num_of_states())
+            .filter(|state| useful[*state] && ways_in[*state] == 0)
+            .collect();
+
+        while let Some(state) = ready.pop() {
+            order.push(state);
+
+            for target in self.transitions[state].iter().filter(|t| useful[**t]) {
+                ways_in[*target] -= 1;
+
+                if ways_in[*target] == 0 {
+                    ready.push(*target);
+                }
+            }
+        }
+
+        if order.len() < useful.iter().filter(|u| **u).count() {
+            return None;
+        }
+
+        return Some(order);
+    }
+
+    // Which states can reach an accepting state, found by walking the transitions backwards from them
+    pub(crate) fn live_states(&self) -> Vec<bool> {
+        let mut incoming: Vec<Vec<StateId>> = vec![Vec::new(); self.num_of_states()];
+
+        for (state, row) in self.transitions.iter().enumerate() {
+            for target in row {
+                incoming[*target].push(state);
+            }
+        }
+
+        let mut live = self.accepting.clone();
+        let mut stack: Vec<StateId> = (0..self.num_of_states()).filter(|s| live[*s]).collect();
+
+        while let Some(state) = stack.pop() {
+            for previous in incoming[state].iter() {
+                if !live[*previous] {
+                    live[*previous] = true;
+                    stack.push(*previous);
+                }
+            }
+        }
+
+        return live;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_pattern_when_complementing_should_match_exactly_what_the_pattern_doesnt() {
+        let keywords = CompleteDfa::new("if|for|while", false).unwrap();
+        let not_keywords = keywords.complement();
+
+        for input in ["if", "for", "while"] {
+            assert!(keywords.is_match(input));
+            assert!(!not_keywords.is_match(input));
+        }
+
+        for input in ["", "i", "iff", "fore", "x", "élan"] {
+            assert!(!keywords.is_match(input), "{}", input);
+            assert!(not_keywords.is_match(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn given_two_patterns_when_combining_should_take_the_intersection_union_and_difference() {
+        let even_as = CompleteDfa::new("(aa)*", false).unwrap();
+        let three_or_more = CompleteDfa::new("aaaa*", false).unwrap();
+
+        let both = even_as.intersection(&three_or_more);
+        let either = even_as.union(&three_or_more);
+        let only_even = even_as.difference(&three_or_more);
+
+        let examples = [
+            ("", false, true, true),
+            ("aa", false, true, true),
+            ("aaa", false, true, false),
+            ("aaaa", true, true, false),
+            ("aaaaa", false, true, false),
+            ("b", false, false, false),
+        ];
+
+        for (input, in_both, in_either, in_only_even) in examples {
+            assert_eq!(both.is_match(input), in_both, "{}", input);
+            assert_eq!(either.is_match(input), in_either, "{}", input);
+            assert_eq!(only_even.is_match(input), in_only_even, "{}", input);
+        }
+    }
+
+    #[test]
+    fn given_complemented_dfa_when_adding_to_an_nfa_should_keep_the_edge_for_every_other_character()
+    {
+        let dfa = CompleteDfa::new("a", false).unwrap().complement();
+        let mut nfa: Graph<AutomataState, AutomataLabel> = Graph::new();
+
+        let component = dfa.add_to_nfa(&mut nfa);
+        nfa.get_node_data_mut(&component.get_accept_state())
+            .unwrap()
+            .mark_as_accepting();
+
+        let round_trip = CompleteDfa::from_nfa(&component, &nfa, SizeLimits::unlimited()).unwrap();
+
+        for input in ["", "a", "b", "ab", "aa", "ü"] {
+            assert_eq!(round_trip.is_match(input), dfa.is_match(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn given_patterns_for_the_same_language_when_comparing_should_find_no_difference() {
+        let examples = [
+            ("(a|b)*", "(a*b*)*"),
+            ("a+", "aa*"),
+            ("(ab)*a", "a(ba)*"),
+            ("~(~(x|y))", "x|y"),
+        ];
+
+        for (left, right) in examples {
+            let left = CompleteDfa::new(left, false).unwrap();
+            let right = CompleteDfa::new(right, false).unwrap();
+
+            assert_eq!(left.find_difference(&right), None);
+        }
+    }
+
+    #[test]
+    fn given_different_languages_when_comparing_should_find_the_shortest_counterexample() {
+        let examples = [
+            ("(a|b)*", "a*b*", Some("ba")),
+            ("ab", "(a|b)*", Some("")),
+            ("a|b|c", "a|c", Some("b")),
+            ("~a", "~(a|b)", Some("b")),
+        ];
+
+        for (left, right, answer) in examples {
+            let left = CompleteDfa::new(left, false).unwrap();
+            let right = CompleteDfa::new(right, false).unwrap();
+
+            assert_eq!(left.find_difference(&right).as_deref(), answer);
+        }
+    }
+
+    #[test]
+    fn given_language_when_checking_it_against_a_superset_should_only_fail_the_other_way_round() {
+        let words = CompleteDfa::new("(a|b)+", false).unwrap();
+        let ab = CompleteDfa::new("ab", false).unwrap();
+
+        assert_eq!(ab.find_not_in(&words), None);
+        assert_eq!(words.find_not_in(&ab).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn given_complement_when_finding_the_shortest_match_should_use_a_readable_character() {
+        let not_a = CompleteDfa::new("~(a*)", false).unwrap();
+
+        assert_eq!(not_a.shortest_match().as_deref(), Some("b"));
+        assert_eq!(
+            CompleteDfa::new("a&b", false).unwrap().shortest_match(),
+            None
+        );
+    }
+
+    #[test]
+    fn given_patterns_when_analysing_should_tell_if_they_match_nothing_or_the_empty_string() {
+        let examples = [
+            ("a", false, false),
+            ("a*", false, true),
+            ("a&b", true, false),
+            ("~(a*)&a*", true, false),
+            ("", false, true),
+        ];
+
+        for (query, nothing, empty) in examples {
+            let dfa = CompleteDfa::new(query, false).unwrap();
+
+            assert_eq!(dfa.matches_nothing(), nothing, "{}", query);
+            assert_eq!(dfa.matches_empty_string(), empty, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_patterns_when_analysing_should_find_finiteness_and_match_lengths() {
+        let examples = [
+            ("dog|cat|ox", true, Some(2), Some(3)),
+            ("ab?c?", true, Some(1), Some(3)),
+            ("x(a|b)*y", false, Some(2), None),
+            ("(a|b)*&aa?", true, Some(1), Some(2)),
+            ("a&b", true, None, None),
+            ("~(a|b)", false, Some(0), None),
+        ];
+
+        for (query, finite, min_len, max_len) in examples {
+            let dfa = CompleteDfa::new(query, false).unwrap();
+
+            assert_eq!(dfa.is_finite(), finite, "{}", query);
+            assert_eq!(dfa.min_match_len(), min_len, "{}", query);
+            assert_eq!(dfa.max_match_len(), max_len, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_patterns_when_counting_matches_of_a_length_should_count_every_character_outside_the_alphabet(
+    ) {
+        let dfa = CompleteDfa::new("(a|b)*c", false).unwrap();
+
+        assert_eq!(dfa.count_matches_of_len(0), Some(0));
+        assert_eq!(dfa.count_matches_of_len(1), Some(1));
+        assert_eq!(dfa.count_matches_of_len(4), Some(8));
+
+        let not_a = CompleteDfa::new("~a", false).unwrap();
+
+        assert_eq!(not_a.count_matches_of_len(0), Some(1));
+        assert_eq!(
+            not_a.count_matches_of_len(1),
+            Some(NUM_OF_CHARS as u128 - 1)
+        );
+
+        // Over a million to the power of 7 doesn't fit
+        assert_eq!(not_a.count_matches_of_len(7), None);
+    }
+
+    #[test]
+    fn given_patterns_for_the_same_language_when_minimizing_should_give_the_same_dfa() {
+        let minimal = |query: &str| CompleteDfa::new(query, false).unwrap().minimize();
+
+        let left = minimal("(a|b)*");
+        let right = minimal("(a*b*)*");
+
+        // One state for strings of a's and b's, and the dead state for everything else
+        assert_eq!(left.num_of_states(), 2);
+        assert_eq!(left.alphabet, right.alphabet);
+        assert_eq!(left.transitions, right.transitions);
+        assert_eq!(left.accepting, right.accepting);
+
+        // Characters that go the same way as everything else drop out of the alphabet
+        let nothing = minimal("a&b");
+
+        assert!(nothing.alphabet.is_empty());
+        assert_eq!(nothing.num_of_states(), 1);
+    }
+
+    #[test]
+    fn given_patterns_when_converting_to_a_regex_should_match_the_same_strings() {
+        let queries = [
+            "(a|b)*abb",
+            "(a|b)+&~(ab)",
+            "~(ab)",
+            "x~(a)y",
+            "a&b",
+            "~(a*)",
+            "~a&~b",
+            "(0|1)*0(0|1)",
+            "",
+        ];
+
+        for query in queries {
+            let dfa = CompleteDfa::new(query, false).unwrap();
+            let regex = dfa.minimize().to_regex();
+            let converted = CompleteDfa::new(&regex, false).unwrap();
+
+            assert_eq!(
+                dfa.find_difference(&converted),
+                None,
+                "{} -> {}",
+                query,
+                regex
+            );
+        }
+    }
+
+    #[test]
+    fn given_complement_when_converting_to_a_regex_should_use_the_complement_operator() {
+        let to_regex = |query: &str| {
+            CompleteDfa::new(query, false)
+                .unwrap()
+                .minimize()
+                .to_regex()
+        };
+
+        assert_eq!(to_regex("~(ab)"), "~(ab)");
+        assert_eq!(to_regex("~(a|b)&~c"), "~(a|b|c)");
+        assert_eq!(to_regex("(a|b)*&~((a|b)*a(a|b)*)"), "b*");
+    }
+}
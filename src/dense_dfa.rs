@@ -0,0 +1,558 @@
+/* A fully built DFA laid out as a flat transition table, so it can be written to disk once and loaded back
+ * without redoing the powerset construction. It's built the same way as the lazy DFA's unanchored searches,
+ * with the empty closure of the NFA start state added into every state, except every state gets built up front.
+ *
+ * Characters are mapped to classes before looking up a transition, so characters the NFA can't tell apart share
+ * a column of the table, see char_classes.rs. Class 0 is everything outside the alphabet. Only the complement of a
+ * pattern can consume a char outside the alphabet, and every one of them goes along the same edges, see
+ * nfa::get_other_char. Only chars outside class 0 get a class entry.
+ *
+ * The binary format is, with every number a little endian u32 unless stated otherwise:
+ *
+ * magic            8 bytes, "MYGREPDF"
+ * version          FORMAT_VERSION
+ * patterns         number of patterns the DFA was built from
+ * classes          number of classes, including class 0
+ * class entries    count, then (char, class) pairs sorted by char
+ * states           number of states
+ * start            index of the start state
+ * transitions      states * classes next state indices, row by row
+ * accepting        for each state, count then the patterns accepting in it
+ * checksum         u64 FNV-1a hash of everything before it
+ */
+
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::{
+    automata::PatternID,
+    builder::{BuildError, SizeLimits},
+    char_classes::{CharClasses, ClassId},
+    dfa::{accepted_patterns, Powerset, StateSetId},
+    nfa::{build_multi_nfa_with, get_nfa_alphabet, NfaConstruction},
+    postfixer,
+    regex::RegexSymbol,
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"MYGREPDF";
+
+type StateId = usize;
+
+pub struct DenseDfa {
+    // Sorted by char, so the class of a char can be binary searched for
+    classes: Vec<(char, ClassId)>,
+    num_of_classes: usize,
+    num_of_patterns: usize,
+    start: StateId,
+    transitions: Vec<StateId>,
+    // The patterns accepting in each state, a state is accepting if it has any
+    accepting: Vec<Vec<PatternID>>,
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl DenseDfa {
+    pub fn new<S: AsRef<str>>(queries: &[S], ignore_case: bool) -> Result<DenseDfa, BuildError> {
+        let mut postfix_regexes: Vec<VecDeque<RegexSymbol>> = Vec::new();
+
+        for query in queries {
+            postfix_regexes.push(postfixer::transform(query.as_ref().to_string())?);
+        }
+
+        return DenseDfa::from_postfix(postfix_regexes, ignore_case, SizeLimits::default());
+    }
+
+    pub fn from_postfix(
+        postfix_regexes: Vec<VecDeque<RegexSymbol>>,
+        ignore_case: bool,
+        limits: SizeLimits,
+    ) -> Result<DenseDfa, BuildError> {
+        limits.check_nfa(&postfix_regexes)?;
+
+        let num_of_patterns = postfix_regexes.len();
+        let (handle, nfa) = build_multi_nfa_with(
+            postfix_regexes,
+            ignore_case,
+            NfaConstruction::default(),
+            limits,
+        )?;
+
+        let alphabet = get_nfa_alphabet(&nfa);
+        let char_classes = CharClasses::new(&nfa, &alphabet);
+
+        // Sorted since the alphabet is, and chars in class 0 don't need an entry
+        let classes: Vec<(char, ClassId)> = alphabet
+            .iter()
+            .map(|c| (*c, char_classes.get_class(*c)))
+            .filter(|(_, class)| *class != 0)
+            .collect();
+        let num_of_classes = char_classes.num_of_classes();
+
+        let mut powerset = Powerset::new(&nfa, &char_classes.representatives());
+
+        let start_set = powerset.closure(&[handle.get_start_state()]);
+        let restart = powerset.get_set(start_set).to_vec();
+
+        // The state for each of powerset's state sets, indexed by the set's id
+        let mut state_ids: Vec<Option<StateId>> = Vec::new();
+        let mut states: Vec<StateSetId> = Vec::new();
+        let mut transitions: Vec<StateId> = Vec::new();
+        let mut accepting: Vec<Vec<PatternID>> = Vec::new();
+
+        state_ids.resize(start_set + 1, None);
+        state_ids[start_set] = Some(0);
+        states.push(start_set);
+
+        // States get numbered in the order they're found, so the rows of the table come out in order too
+        let mut current = 0;
+
+        while current < states.len() {
+            let state = states[current];
+
+            accepting.push(
+                accepted_patterns(&nfa, powerset.get_set(state).iter())
+                    .into_iter()
+                    .collect(),
+            );
+
+            // Start a new match attempt at the next position as well
+            let next_states = powerset.step(state, &restart);
+            state_ids.resize(powerset.num_of_sets(), None);
+
+            for next in next_states {
+                let next_id = match state_ids[next] {
+                    Some(id) => id,
+                    None => {
+                        // Building every state up front is exponential in the worst case, and each one
+                        // takes a whole row of the table
+                        if states.len() == limits.dfa_states {
+                            return Err(BuildError::TooManyDfaStates {
+                                limit: limits.dfa_states,
+                            });
+                        }
+
+                        if (states.len() + 1) * num_of_classes > limits.dfa_transitions {
+                            return Err(BuildError::TooManyDfaTransitions {
+                                limit: limits.dfa_transitions,
+                            });
+                        }
+
+                        let id = states.len();
+
+                        state_ids[next] = Some(id);
+                        states.push(next);
+
+                        id
+                    }
+                };
+
+                transitions.push(next_id);
+            }
+
+            current += 1;
+        }
+
+        return Ok(DenseDfa {
+            classes,
+            num_of_classes,
+            num_of_patterns,
+            start: 0,
+            transitions,
+            accepting,
+        });
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        let mut state = self.start;
+
+        if !self.accepting[state].is_empty() {
+            return true;
+        }
+
+        for c in haystack.chars() {
+            state = self.next_state(state, c);
+
+            if !self.accepting[state].is_empty() {
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    // Returns the index of every pattern with a match in the haystack, in increasing order
+    pub fn matches(&self, haystack: &str) -> Vec<PatternID> {
+        let mut state = self.start;
+        let mut found: BTreeSet<PatternID> = self.accepting[state].iter().copied().collect();
+
+        for c in haystack.chars() {
+            if found.len() == self.num_of_patterns {
+                break;
+            }
+
+            state = self.next_state(state, c);
+            found.extend(self.accepting[state].iter());
+        }
+
+        return found.into_iter().collect();
+    }
+
+    pub fn num_of_states(&self) -> usize {
+        return self.accepting.len();
+    }
+
+    pub fn num_of_classes(&self) -> usize {
+        return self.num_of_classes;
+    }
+
+    pub fn num_of_patterns(&self) -> usize {
+        return self.num_of_patterns;
+    }
+
+    pub(crate) fn get_start(&self) -> StateId {
+        return self.start;
+    }
+
+    pub(crate) fn is_accepting(&self, state: StateId) -> bool {
+        return !self.accepting[state].is_empty();
+    }
+
+    pub(crate) fn next_state(&self, state: StateId, c: char) -> StateId {
+        let class = match self.classes.binary_search_by_key(&c, |(c, _)| *c) {
+            Ok(i) => self.classes[i].1,
+            Err(_) => 0,
+        };
+
+        return self.transitions[state * self.num_of_classes + class];
+    }
+
+    // Every number is written as a u32, so a DFA built without limits can have ones too big to save
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut write = |value: usize| -> Result<(), String> {
+            let value = u32::try_from(value).map_err(|_| {
+                format!(
+                    "Error - The DFA is too big to save, {} doesn't fit in 32 bits",
+                    value
+                )
+            })?;
+
+            bytes.extend(value.to_le_bytes());
+            return Ok(());
+        };
+
+        write(FORMAT_VERSION as usize)?;
+        write(self.num_of_patterns)?;
+        write(self.num_of_classes)?;
+
+        write(self.classes.len())?;
+        for (c, class) in self.classes.iter() {
+            write(*c as usize)?;
+            write(*class)?;
+        }
+
+        write(self.num_of_states())?;
+        write(self.start)?;
+
+        for next in self.transitions.iter() {
+            write(*next)?;
+        }
+
+        for patterns in self.accepting.iter() {
+            write(patterns.len())?;
+
+            for pattern in patterns {
+                write(*pattern)?;
+            }
+        }
+
+        let mut result = MAGIC.to_vec();
+        result.extend(bytes);
+
+        let checksum = fnv1a(&result);
+        result.extend(checksum.to_le_bytes());
+
+        return Ok(result);
+    }
+
+    // Checks everything a search relies on, so a corrupt or hand edited file is rejected here rather
+    // than sending a search off the end of the transition table
+    pub fn from_bytes(bytes: &[u8]) -> Result<DenseDfa, String> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("Error - Not a saved DFA".to_string());
+        }
+
+        if bytes.len() < MAGIC.len() + 8 {
+            return Err("Error - Saved DFA is truncated".to_string());
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - 8);
+
+        let mut reader = ByteReader {
+            bytes: body,
+            at: MAGIC.len(),
+        };
+
+        let version = reader.read()?;
+
+        if version != FORMAT_VERSION as usize {
+            return Err(format!(
+                "Error - Saved DFA has format version {}, expected {}",
+                version, FORMAT_VERSION
+            ));
+        }
+
+        if u64::from_le_bytes(checksum.try_into().unwrap()) != fnv1a(body) {
+            return Err("Error - Saved DFA is corrupt, its checksum doesn't match".to_string());
+        }
+
+        let num_of_patterns = reader.read()?;
+        let num_of_classes = reader.read()?;
+
+        if num_of_classes == 0 {
+            return Err("Error - Saved DFA has no classes".to_string());
+        }
+
+        let num_of_entries = reader.read()?;
+        let mut classes: Vec<(char, ClassId)> = Vec::new();
+
+        for _ in 0..num_of_entries {
+            let c = char::from_u32(reader.read()? as u32)
+                .ok_or("Error - Saved DFA has an invalid character")?;
+            let class = reader.read_below(num_of_classes, "class")?;
+
+            if classes.last().is_some_and(|(last, _)| *last >= c) {
+                return Err("Error - Saved DFA's classes aren't sorted".to_string());
+            }
+
+            classes.push((c, class));
+        }
+
+        let num_of_states = reader.read()?;
+
+        if num_of_states == 0 {
+            return Err("Error - Saved DFA has no states".to_string());
+        }
+
+        let start = reader.read_below(num_of_states, "state")?;
+
+        let mut transitions: Vec<StateId> = Vec::new();
+
+        for _ in 0..num_of_states * num_of_classes {
+            transitions.push(reader.read_below(num_of_states, "state")?);
+        }
+
+        let mut accepting: Vec<Vec<PatternID>> = Vec::new();
+
+        for _ in 0..num_of_states {
+            let count = reader.read()?;
+            let mut patterns: Vec<PatternID> = Vec::new();
+
+            for _ in 0..count {
+                patterns.push(reader.read_below(num_of_patterns, "pattern")?);
+            }
+
+            accepting.push(patterns);
+        }
+
+        if reader.at != body.len() {
+            return Err("Error - Saved DFA has unexpected trailing data".to_string());
+        }
+
+        return Ok(DenseDfa {
+            classes,
+            num_of_classes,
+            num_of_patterns,
+            start,
+            transitions,
+            accepting,
+        });
+    }
+}
+
+impl<'a> ByteReader<'a> {
+    fn read(&mut self) -> Result<usize, String> {
+        let end = self.at + 4;
+
+        if end > self.bytes.len() {
+            return Err("Error - Saved DFA is truncated".to_string());
+        }
+
+        let value = u32::from_le_bytes(self.bytes[self.at..end].try_into().unwrap());
+        self.at = end;
+
+        return Ok(value as usize);
+    }
+
+    fn read_below(&mut self, limit: usize, what: &str) -> Result<usize, String> {
+        let value = self.read()?;
+
+        if value >= limit {
+            return Err(format!(
+                "Error - Saved DFA refers to {} {}, but only has {}",
+                what, value, limit
+            ));
+        }
+
+        return Ok(value);
+    }
+}
+
+// Link: http://www.isthe.com/chongo/tech/comp/fnv/index.html
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.truncate(bytes.len() - 8);
+
+        let checksum = fnv1a(&bytes);
+        bytes.extend(checksum.to_le_bytes());
+
+        return bytes;
+    }
+
+    #[test]
+    fn given_patterns_when_matching_should_report_the_same_as_the_other_engines() {
+        let dfa = DenseDfa::new(&["you", "us", "(a|e)+r", "frog"], false).unwrap();
+
+        let examples = [
+            ("I'm nobody! Who are you?", vec![0, 2]),
+            ("They'd banish us, you know.", vec![0, 1]),
+            ("How public, like a frog", vec![3]),
+            ("To an admiring bog!", vec![]),
+        ];
+
+        for (haystack, answer) in examples {
+            assert_eq!(dfa.matches(haystack), answer, "{}", haystack);
+            assert_eq!(dfa.is_match(haystack), !answer.is_empty());
+        }
+    }
+
+    #[test]
+    fn given_ignore_case_when_building_should_put_the_folded_characters_in_the_alphabet() {
+        let dfa = DenseDfa::new(&["rUsT"], true).unwrap();
+
+        assert!(dfa.is_match("Trust me."));
+        assert!(dfa.is_match("TRUST ME."));
+        assert!(!dfa.is_match("Rus ty"));
+
+        // Everything else, then r, u, s and t with both their cases, plus the long s
+        assert_eq!(dfa.num_of_classes(), 5);
+    }
+
+    #[test]
+    fn given_saved_dfa_when_loading_should_get_back_the_same_automaton() {
+        let dfa = DenseDfa::new(&["(you)|(us)", "é+"], false).unwrap();
+        let loaded = DenseDfa::from_bytes(&dfa.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(loaded.num_of_states(), dfa.num_of_states());
+        assert_eq!(loaded.num_of_classes(), dfa.num_of_classes());
+        assert_eq!(loaded.num_of_patterns(), 2);
+
+        assert_eq!(loaded.matches("café, you?"), vec![0, 1]);
+        assert_eq!(loaded.matches("cafe"), vec![]);
+        assert_eq!(loaded.to_bytes(), dfa.to_bytes());
+    }
+
+    #[test]
+    fn given_dfa_with_numbers_over_32_bits_when_saving_should_error_rather_than_truncate() {
+        let mut dfa = DenseDfa::new(&["a"], false).unwrap();
+
+        dfa.num_of_patterns = u32::MAX as usize + 1;
+
+        assert_eq!(
+            dfa.to_bytes().err().unwrap(),
+            "Error - The DFA is too big to save, 4294967296 doesn't fit in 32 bits"
+        );
+    }
+
+    #[test]
+    fn given_invalid_bytes_when_loading_should_reject_them() {
+        let dfa = DenseDfa::new(&["ab|c"], false).unwrap();
+        let bytes = dfa.to_bytes().unwrap();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 99;
+
+        // The start state comes straight after the 3 class entries for a, b and c
+        let mut corrupted = bytes.clone();
+        corrupted[52] = 200;
+
+        let out_of_range = format!(
+            "Error - Saved DFA refers to state 200, but only has {}",
+            dfa.num_of_states()
+        );
+
+        let examples = [
+            (wrong_magic, "Error - Not a saved DFA"),
+            (
+                wrong_version.clone(),
+                "Error - Saved DFA has format version 99, expected 1",
+            ),
+            (
+                corrupted.clone(),
+                "Error - Saved DFA is corrupt, its checksum doesn't match",
+            ),
+            (with_checksum(corrupted), out_of_range.as_str()),
+            (
+                bytes[..bytes.len() - 3].to_vec(),
+                "Error - Saved DFA is corrupt, its checksum doesn't match",
+            ),
+            (
+                with_checksum(bytes[..bytes.len() - 12].to_vec()),
+                "Error - Saved DFA is truncated",
+            ),
+        ];
+
+        for (bytes, answer) in examples {
+            assert_eq!(DenseDfa::from_bytes(&bytes).err().unwrap(), answer);
+        }
+    }
+
+    #[test]
+    fn given_pattern_whose_dfa_is_too_big_when_building_should_give_up() {
+        let query = format!("(a|b)*a{}", "(a|b)".repeat(12));
+        let postfix_regex = || postfixer::transform(query.clone()).unwrap();
+
+        let limits = SizeLimits {
+            dfa_states: 1000,
+            ..SizeLimits::default()
+        };
+        let result = DenseDfa::from_postfix(vec![postfix_regex()], false, limits);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Error - The DFA needs more than 1000 states"
+        );
+
+        let limits = SizeLimits {
+            dfa_transitions: 1000,
+            ..SizeLimits::default()
+        };
+        let result = DenseDfa::from_postfix(vec![postfix_regex()], false, limits);
+
+        assert_eq!(
+            result.err(),
+            Some(BuildError::TooManyDfaTransitions { limit: 1000 })
+        );
+    }
+}
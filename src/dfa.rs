@@ -32,98 +32,451 @@
  *        add t to Q and worklist
  *
  * Note: delta gets the neighbours of each sub state by taking an edge labelled with char c from it.
+ *
+ * Done naively, the empty closure of every (q, c) gets chased through the NFA from scratch, which is most of the
+ * work for big alternations where each q has hundreds of sub states. Powerset works out each NFA node's empty
+ * closure the first time it's needed and keeps it, and takes every char out of q in one pass over its edges. Each
+ * DFA state, a sorted slice of NFA nodes, is interned as an integer id the first time it turns up, so it's only
+ * hashed once there and everything after that (is t in Q, T[q,c]) is indexing by id. Chars the NFA can't tell
+ * apart are in the same class (see char_classes.rs), so only one char of each class gets stepped.
  */
 
 use std::{
     collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    mem,
     rc::Rc,
+    sync::Arc,
 };
 
 use crate::{
-    automata::{AutomataComponent, AutomataLabel, AutomataState},
+    automata::{AutomataComponent, AutomataLabel, AutomataState, PatternID},
+    builder::{BuildError, SizeLimits},
+    char_classes::CharClasses,
     graph::{Graph, NodeIndex},
 };
 
 // Use a BTreeSet because it implements Hash since it stores it's elements in sorted order.
-type DFAState = BTreeSet<NodeIndex>;
+pub(crate) type DFAState = BTreeSet<NodeIndex>;
+
+// A DFA state, or one of the lazy DFA's groups, as the NFA states making it up in sorted order. Powerset keeps one
+// of each and hands out its StateSetId instead.
+pub(crate) type StateSet = Arc<[NodeIndex]>;
+
+// Which StateSet Powerset interned, numbered in the order they were first built from 0
+pub(crate) type StateSetId = usize;
+
+// The NFA laid out for the powerset construction. The edges taking a character are pulled out of the graph once
+// rather than borrowed every time, and each node's empty closure gets worked out the first time it's needed and
+// kept, so the closure of a set of nodes is just the union of theirs. The closures can add up to the square of the
+// NFA, so past the closure budget any more get worked out again each time instead of kept.
+//
+// Sets are built up using marks rather than searching them: a node is in the set being built if its mark is the
+// current generation, so emptying the set is just moving on to the next generation.
+//
+// The edges never change, so they're shared between clones, e.g. every lazy DFA cache in a pool.
+#[derive(Clone)]
+pub(crate) struct Powerset {
+    alphabet: Vec<char>,
+    columns: HashMap<char, usize>,
+    edges: Arc<Vec<Vec<(AutomataLabel, NodeIndex)>>>,
+    empty_edges: Arc<Vec<Vec<NodeIndex>>>,
+    closures: Vec<Option<StateSet>>,
+    marks: Vec<usize>,
+    generation: usize,
+    // Every state set built so far, indexed by its id
+    sets: Vec<StateSet>,
+    ids: HashMap<StateSet, StateSetId>,
+    // Roughly how many bytes the closures and sets take up
+    closures_memory: usize,
+    sets_memory: usize,
+    closure_budget: usize,
+}
+
+impl Powerset {
+    pub(crate) fn new(nfa: &Graph<AutomataState, AutomataLabel>, alphabet: &[char]) -> Powerset {
+        let mut edges: Vec<Vec<(AutomataLabel, NodeIndex)>> = Vec::new();
+        let mut empty_edges: Vec<Vec<NodeIndex>> = Vec::new();
+
+        for node in 0..nfa.num_of_nodes() {
+            let mut taking: Vec<(AutomataLabel, NodeIndex)> = Vec::new();
+            let mut empty: Vec<NodeIndex> = Vec::new();
+
+            for edge in nfa.outgoing_edges(node).unwrap() {
+                let label = nfa.get_edge_data(&edge).unwrap().clone();
+                let target = nfa.traverse(edge).unwrap();
+
+                if label.is_empty() {
+                    empty.push(target);
+                } else {
+                    taking.push((label, target));
+                }
+            }
+
+            edges.push(taking);
+            empty_edges.push(empty);
+        }
+
+        // Any repeats of a character would only lead to the same state again
+        let mut deduped: Vec<char> = Vec::new();
+        let mut columns: HashMap<char, usize> = HashMap::new();
+
+        for c in alphabet {
+            if !columns.contains_key(c) {
+                columns.insert(*c, deduped.len());
+                deduped.push(*c);
+            }
+        }
+
+        return Powerset {
+            alphabet: deduped,
+            columns,
+            edges: Arc::new(edges),
+            empty_edges: Arc::new(empty_edges),
+            closures: vec![None; nfa.num_of_nodes()],
+            marks: vec![0; nfa.num_of_nodes()],
+            generation: 0,
+            sets: Vec::new(),
+            ids: HashMap::new(),
+            closures_memory: 0,
+            sets_memory: 0,
+            closure_budget: usize::MAX,
+        };
+    }
+
+    // The most bytes of closures to keep
+    pub(crate) fn with_closure_budget(mut self, closure_budget: usize) -> Powerset {
+        self.closure_budget = closure_budget;
+        return self;
+    }
+
+    // The NFA nodes making up the state set
+    pub(crate) fn get_set(&self, id: StateSetId) -> &[NodeIndex] {
+        return &self.sets[id];
+    }
+
+    pub(crate) fn num_of_sets(&self) -> usize {
+        return self.sets.len();
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        return self.closures_memory + self.sets_memory;
+    }
+
+    // Forgets every set, and so every id handed out so far, for the lazy DFA to flush its cache. The closures are
+    // kept since they never change.
+    pub(crate) fn clear_sets(&mut self) {
+        self.sets.clear();
+        self.ids.clear();
+        self.sets_memory = 0;
+    }
+
+    // The id of the set, which has to be sorted
+    pub(crate) fn intern(&mut self, set: &[NodeIndex]) -> StateSetId {
+        if let Some(id) = self.ids.get(set) {
+            return *id;
+        }
+
+        let set: StateSet = set.into();
+        let id = self.sets.len();
+
+        self.sets_memory += mem::size_of::<(StateSet, StateSet, StateSetId)>()
+            + set.len() * mem::size_of::<NodeIndex>();
+        self.sets.push(set.clone());
+        self.ids.insert(set, id);
+
+        return id;
+    }
+
+    // The empty closure of the nodes
+    pub(crate) fn closure(&mut self, nodes: &[NodeIndex]) -> StateSetId {
+        let closures: Vec<StateSet> = nodes.iter().map(|node| self.node_closure(*node)).collect();
+
+        self.generation += 1;
+
+        let mut result: Vec<NodeIndex> = Vec::new();
+
+        for closure in closures {
+            for reached in closure.iter() {
+                if self.marks[*reached] != self.generation {
+                    self.marks[*reached] = self.generation;
+                    result.push(*reached);
+                }
+            }
+        }
+
+        result.sort_unstable();
+
+        return self.intern(&result);
+    }
+
+    // The state reached from the state on each character of the alphabet, in the same order, along with the restart
+    // nodes. A state with nothing in it means there's no transition on that character.
+    pub(crate) fn step(&mut self, state: StateSetId, restart: &[NodeIndex]) -> Vec<StateSetId> {
+        let mut moved: Vec<Vec<NodeIndex>> = vec![Vec::new(); self.alphabet.len()];
+        let state = self.sets[state].clone();
+
+        // Going through each edge once and sorting its target into the right character is far quicker than going
+        // through every edge again for each character
+        for node in state.iter() {
+            for (label, target) in self.edges[*node].iter() {
+                match label.get_label() {
+                    Some(c) => {
+                        if let Some(column) = self.columns.get(&c) {
+                            moved[*column].push(*target);
+                        }
+                    }
+                    None => {
+                        for (column, c) in self.alphabet.iter().enumerate() {
+                            if label.matches(*c) {
+                                moved[column].push(*target);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        return moved
+            .into_iter()
+            .map(|mut targets| {
+                targets.extend(restart.iter());
+
+                return self.closure(&targets);
+            })
+            .collect();
+    }
+
+    // The groups the lazy DFA's groups go to on the character, in the same order, followed by the restart nodes,
+    // which have to be an empty closure already. A node only goes in the first group to reach it, since any later
+    // group started further right, and groups left with nothing in them are dropped. Merged, it's all one group.
+    pub(crate) fn step_groups(
+        &mut self,
+        groups: &[StateSetId],
+        c: char,
+        restart: &[NodeIndex],
+        merge: bool,
+    ) -> Vec<StateSetId> {
+        let mut moved: Vec<Vec<NodeIndex>> = Vec::new();
+
+        for group in groups {
+            let mut targets: Vec<NodeIndex> = Vec::new();
+
+            for node in self.sets[*group].iter() {
+                for (label, target) in self.edges[*node].iter() {
+                    if label.matches(c) {
+                        targets.push(*target);
+                    }
+                }
+            }
+
+            moved.push(targets);
+        }
+
+        // Working out a closure uses the marks too, so they all have to be done before the groups get built up
+        let moved: Vec<Vec<StateSet>> = moved
+            .into_iter()
+            .map(|targets| {
+                targets
+                    .iter()
+                    .map(|target| self.node_closure(*target))
+                    .collect()
+            })
+            .collect();
+
+        self.generation += 1;
+
+        let mut result: Vec<StateSetId> = Vec::new();
+        let mut group: Vec<NodeIndex> = Vec::new();
+
+        for closures in moved {
+            for closure in closures {
+                for reached in closure.iter() {
+                    if self.marks[*reached] != self.generation {
+                        self.marks[*reached] = self.generation;
+                        group.push(*reached);
+                    }
+                }
+            }
+
+            if !merge {
+                self.finish_group(&mut group, &mut result);
+            }
+        }
+
+        for node in restart {
+            if self.marks[*node] != self.generation {
+                self.marks[*node] = self.generation;
+                group.push(*node);
+            }
+        }
+
+        self.finish_group(&mut group, &mut result);
+
+        return result;
+    }
+
+    fn finish_group(&mut self, group: &mut Vec<NodeIndex>, result: &mut Vec<StateSetId>) {
+        if !group.is_empty() {
+            group.sort_unstable();
+            result.push(self.intern(group));
+            group.clear();
+        }
+    }
+
+    fn node_closure(&mut self, node: NodeIndex) -> StateSet {
+        if let Some(closure) = &self.closures[node] {
+            return closure.clone();
+        }
+
+        self.generation += 1;
+
+        let mut result: Vec<NodeIndex> = Vec::new();
+        let mut visit_stack: Vec<NodeIndex> = vec![node];
+
+        self.marks[node] = self.generation;
+
+        while let Some(current) = visit_stack.pop() {
+            result.push(current);
+
+            for next in self.empty_edges[current].iter() {
+                if self.marks[*next] != self.generation {
+                    self.marks[*next] = self.generation;
+                    visit_stack.push(*next);
+                }
+            }
+        }
+
+        result.sort_unstable();
+
+        let closure: StateSet = result.into();
+        let cost = mem::size_of::<StateSet>() + closure.len() * mem::size_of::<NodeIndex>();
+
+        if self.closures_memory + cost <= self.closure_budget {
+            self.closures_memory += cost;
+            self.closures[node] = Some(closure.clone());
+        }
+
+        return closure;
+    }
+}
 
 pub fn build_dfa(
     handle: AutomataComponent,
-    nfa: Graph<AutomataState, AutomataLabel>,
+    nfa: &Graph<AutomataState, AutomataLabel>,
     alphabet: Vec<char>,
 ) -> (NodeIndex, Graph<AutomataState, char>) {
+    return build_dfa_with_limits(handle, nfa, alphabet, SizeLimits::unlimited())
+        .expect("Error - A DFA without limits can't go over them");
+}
+
+// Same as build_dfa, but gives up as soon as the DFA goes over the state or transition limit
+pub fn build_dfa_with_limits(
+    handle: AutomataComponent,
+    nfa: &Graph<AutomataState, AutomataLabel>,
+    alphabet: Vec<char>,
+    limits: SizeLimits,
+) -> Result<(NodeIndex, Graph<AutomataState, char>), BuildError> {
     let mut dfa: Graph<AutomataState, char> = Graph::new();
+    let classes = CharClasses::new(nfa, &alphabet);
+    let mut powerset = Powerset::new(nfa, &classes.representatives());
+
+    // Any repeats of a character would only lead to the same state again
+    let mut seen: HashSet<char> = HashSet::new();
+    let alphabet: Vec<char> = alphabet.into_iter().filter(|c| seen.insert(*c)).collect();
 
-    let start = handle.get_start_state();
     let accept = handle.get_accept_state();
 
-    let start_of_dfa = Rc::new(empty_closure(&nfa, Rc::new(BTreeSet::from([start]))));
-    let is_start_accepting = start_of_dfa.contains(&accept);
-    let start_index = dfa.add_node(AutomataState::new(is_start_accepting));
+    let start_of_dfa = powerset.closure(&[handle.get_start_state()]);
+    let start_set = powerset.get_set(start_of_dfa);
+    let start_index = dfa.add_node(AutomataState::with_patterns(
+        start_set.binary_search(&accept).is_ok(),
+        accepted_patterns(nfa, start_set.iter()),
+    ));
 
-    let mut final_dfa_edges: HashSet<(NodeIndex, NodeIndex, char)> = HashSet::new();
-    let mut final_dfa_states: HashMap<Rc<DFAState>, NodeIndex> =
-        HashMap::from([(start_of_dfa.clone(), start_index)]);
+    let mut num_of_transitions = 0;
+    // The DFA node for each state set, indexed by its id
+    let mut final_dfa_states: Vec<Option<NodeIndex>> = Vec::new();
+    final_dfa_states.resize(start_of_dfa + 1, None);
+    final_dfa_states[start_of_dfa] = Some(start_index);
 
-    let mut worklist: VecDeque<(Rc<DFAState>, NodeIndex)> = VecDeque::new();
-    worklist.push_back((start_of_dfa.clone(), start_index));
+    let mut worklist: VecDeque<(StateSetId, NodeIndex)> = VecDeque::new();
+    worklist.push_back((start_of_dfa, start_index));
 
-    while !worklist.is_empty() {
-        let (current, index) = worklist.pop_front().unwrap();
+    while let Some((current, index)) = worklist.pop_front() {
+        // Only one char from each class needs stepping through the NFA, the rest of the class goes to the same state
+        let next_states = powerset.step(current, &[]);
+        final_dfa_states.resize(powerset.num_of_sets(), None);
 
         for c in alphabet.iter() {
-            let available_neighbours = Rc::new(delta(&nfa, current.clone(), *c));
-            let next = Rc::new(empty_closure(&nfa, available_neighbours.clone()));
+            let next = next_states[classes.get_class(*c)];
+            let next_set = powerset.get_set(next);
 
             // Guard against adding empty states, i.e. the delta and empty closure returned nothing so there's no deterministic transition to be made on c
-            if !next.is_empty() {
-                let next_index: usize;
+            if next_set.is_empty() {
+                continue;
+            }
 
-                if !final_dfa_states.contains_key(&next) {
-                    let is_next_accepting = next.contains(&accept);
-                    next_index = dfa.add_node(AutomataState::new(is_next_accepting));
+            let next_index = match final_dfa_states[next] {
+                Some(next_index) => next_index,
+                None => {
+                    if dfa.num_of_nodes() == limits.dfa_states {
+                        return Err(BuildError::TooManyDfaStates {
+                            limit: limits.dfa_states,
+                        });
+                    }
 
-                    final_dfa_states.insert(next.clone(), next_index);
-                    worklist.push_back((next.clone(), next_index));
-                } else {
-                    next_index = *final_dfa_states.get(&next).unwrap();
-                }
+                    let next_index = dfa.add_node(AutomataState::with_patterns(
+                        next_set.binary_search(&accept).is_ok(),
+                        accepted_patterns(nfa, next_set.iter()),
+                    ));
+
+                    final_dfa_states[next] = Some(next_index);
+                    worklist.push_back((next, next_index));
 
-                // Guard against adding duplicate edges
-                if !final_dfa_edges.contains(&(index, next_index, *c)) {
-                    final_dfa_edges.insert((index, next_index, *c));
-                    dfa.add_edge(index, next_index, *c);
+                    next_index
                 }
+            };
+
+            // The alphabet has no repeats, so there's only ever one edge from a state on a character
+            if num_of_transitions == limits.dfa_transitions {
+                return Err(BuildError::TooManyDfaTransitions {
+                    limit: limits.dfa_transitions,
+                });
             }
+
+            num_of_transitions += 1;
+            dfa.add_edge(index, next_index, *c);
         }
     }
 
-    return (start_index, dfa);
+    return Ok((start_index, dfa));
 }
 
 // Using a depth-first search here to do the empty closure
-fn empty_closure(nfa: &Graph<AutomataState, AutomataLabel>, from: Rc<DFAState>) -> DFAState {
+pub(crate) fn empty_closure(
+    nfa: &Graph<AutomataState, AutomataLabel>,
+    from: Rc<DFAState>,
+) -> DFAState {
     let mut result: DFAState = BTreeSet::new();
     let mut visit_stack: Vec<NodeIndex> = Vec::new();
 
+    // Nodes go in the result as soon as they're found, so checking whether one's been seen is just a lookup
     for state in from.iter() {
-        visit_stack.push(*state);
+        if result.insert(*state) {
+            visit_stack.push(*state);
+        }
     }
 
-    while !visit_stack.is_empty() {
-        let current = visit_stack.pop().unwrap();
-        result.insert(current);
-
+    while let Some(current) = visit_stack.pop() {
         let outgoing_edges = nfa.outgoing_edges(current).unwrap();
 
         for edge in outgoing_edges {
-            let data = nfa.get_edge_data(&edge).unwrap().clone();
-            let label = (*data).borrow().get_label();
+            let data = nfa.get_edge_data(&edge).unwrap();
 
-            if label == None {
+            if data.is_empty() {
                 let next = nfa.traverse(edge).unwrap();
 
-                if !result.contains(&next) && !visit_stack.contains(&next) {
+                if result.insert(next) {
                     visit_stack.push(next)
                 }
             }
@@ -133,25 +486,126 @@ fn empty_closure(nfa: &Graph<AutomataState, AutomataLabel>, from: Rc<DFAState>)
     return result;
 }
 
-fn delta(nfa: &Graph<AutomataState, AutomataLabel>, from: Rc<DFAState>, c: char) -> DFAState {
+// The patterns accepted by any of the NFA states making up the DFA state
+pub(crate) fn accepted_patterns<'a>(
+    nfa: &Graph<AutomataState, AutomataLabel>,
+    state: impl IntoIterator<Item = &'a NodeIndex>,
+) -> BTreeSet<PatternID> {
+    let mut result: BTreeSet<PatternID> = BTreeSet::new();
+
+    for sub_state in state {
+        let data = nfa.get_node_data(sub_state).unwrap();
+        result.extend(data.get_patterns().iter());
+    }
+
+    return result;
+}
+
+pub(crate) fn delta(
+    nfa: &Graph<AutomataState, AutomataLabel>,
+    from: Rc<DFAState>,
+    c: char,
+) -> DFAState {
     let mut result: BTreeSet<NodeIndex> = BTreeSet::new();
 
     for state in (*from).iter() {
         let outgoing_edges = nfa.outgoing_edges(*state).unwrap();
 
         for edge in outgoing_edges {
-            let data = nfa.get_edge_data(&edge).unwrap().clone();
-            let label = (*data).borrow().get_label();
+            let data = nfa.get_edge_data(&edge).unwrap();
 
-            match label {
-                Some(s) if s == c => {
-                    let target = nfa.traverse(edge).unwrap();
-                    result.insert(target);
-                }
-                _ => (),
+            if data.matches(c) {
+                let target = nfa.traverse(edge).unwrap();
+                result.insert(target);
             }
         }
     }
 
     return result;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{nfa::build_nfa, postfixer, regex::get_alphabet};
+
+    fn build(query: &str) -> (NodeIndex, Graph<AutomataState, char>) {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let alphabet = get_alphabet(&postfix_regex);
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+
+        return build_dfa(handle, &nfa, alphabet);
+    }
+
+    fn is_match(dfa: &(NodeIndex, Graph<AutomataState, char>), haystack: &str) -> bool {
+        let (mut state, graph) = (dfa.0, &dfa.1);
+
+        for c in haystack.chars() {
+            let next = graph
+                .outgoing_edges(state)
+                .unwrap()
+                .into_iter()
+                .find(|edge| *graph.get_edge_data(edge).unwrap() == c);
+
+            match next {
+                Some(edge) => state = graph.traverse(edge).unwrap(),
+                None => return false,
+            }
+        }
+
+        return graph.get_node_data(&state).unwrap().is_accepting();
+    }
+
+    #[test]
+    fn given_alphabet_with_repeats_when_building_dfa_should_only_add_one_edge_per_char() {
+        let postfix_regex = postfixer::transform("(a|b)*abb".to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+        let (_, dfa) = build_dfa(handle, &nfa, vec!['a', 'b', 'a', 'b', 'b']);
+
+        // Every state can still take a or b on the way to another abb
+        assert_eq!(dfa.num_of_edges(), 2 * dfa.num_of_nodes());
+    }
+
+    #[test]
+    fn given_nodes_when_taking_closure_should_match_empty_closure() {
+        let postfix_regex = postfixer::transform("(a*|b?)*(c|d+)e?".to_string()).unwrap();
+        let (_, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+        let mut powerset = Powerset::new(&nfa, &[]);
+
+        for node in 0..nfa.num_of_nodes() {
+            let expected = empty_closure(&nfa, Rc::new(BTreeSet::from([node, 0])));
+
+            let closure = powerset.closure(&[node, 0]);
+
+            assert!(powerset.get_set(closure).iter().eq(expected.iter()));
+        }
+    }
+
+    #[test]
+    fn given_same_nodes_when_taking_closure_should_give_the_same_id() {
+        let postfix_regex = postfixer::transform("(a|b)*abb".to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+        let mut powerset = Powerset::new(&nfa, &['a', 'b']);
+
+        let start = powerset.closure(&[handle.get_start_state()]);
+        let stepped = powerset.step(start, &[]);
+
+        assert_eq!(powerset.closure(&[handle.get_start_state()]), start);
+        assert_eq!(powerset.step(start, &[]), stepped);
+        assert_ne!(stepped[0], start);
+        assert_eq!(powerset.num_of_sets(), 3);
+    }
+
+    #[test]
+    fn given_large_alternation_when_building_dfa_should_match() {
+        let words: Vec<String> = (0..500).map(|i| format!("word{}x", i)).collect();
+        let query = words.join("|");
+
+        let dfa = build(&query);
+
+        assert!(is_match(&dfa, "word0x"));
+        assert!(is_match(&dfa, "word499x"));
+        assert!(!is_match(&dfa, "word500x"));
+        assert!(!is_match(&dfa, "word4"));
+    }
+}
@@ -0,0 +1,244 @@
+/* Writes automata out in the Graphviz DOT language, so they can be drawn with e.g.
+ *
+ * cargo run -- --dump-nfa "(you)|(us)" | dot -Tsvg > nfa.svg
+ *
+ * Parallel edges between the same two states are drawn as one edge, with runs of consecutive characters
+ * collapsed into ranges, otherwise case folding turns every edge into a pair and alternations like a|b|c|d
+ * into a wall of arrows. Accepting states get a double circle along with the patterns they accept.
+ *
+ * Link: https://graphviz.org/doc/info/lang.html
+ */
+
+use std::collections::BTreeMap;
+
+use crate::{
+    automata::{AutomataLabel, AutomataState},
+    graph::{Graph, NodeIndex},
+};
+
+// What gets drawn on an edge, None being an empty transition
+pub trait DotLabel {
+    fn get_dot_label(&self) -> Option<char>;
+
+    // For edges taken on any character except these, which are drawn on their own
+    fn get_dot_excluded(&self) -> Option<Vec<char>> {
+        return None;
+    }
+}
+
+impl DotLabel for AutomataLabel {
+    fn get_dot_label(&self) -> Option<char> {
+        return self.get_label();
+    }
+
+    fn get_dot_excluded(&self) -> Option<Vec<char>> {
+        return self
+            .get_excluded()
+            .map(|excluded| excluded.iter().copied().collect());
+    }
+}
+
+impl DotLabel for char {
+    fn get_dot_label(&self) -> Option<char> {
+        return Some(*self);
+    }
+}
+
+pub fn to_dot<U: DotLabel>(
+    name: &str,
+    graph: &Graph<AutomataState, U>,
+    start: NodeIndex,
+) -> String {
+    let mut dot = format!("digraph {} {{\n", name);
+
+    dot.push_str("    rankdir=LR;\n");
+    dot.push_str("    start [shape=point];\n");
+
+    for node in 0..graph.num_of_nodes() {
+        let data = graph.get_node_data(&node).unwrap();
+
+        if !data.is_accepting() {
+            dot.push_str(&format!(
+                "    n{} [shape=circle, label=\"{}\"];\n",
+                node, node
+            ));
+            continue;
+        }
+
+        let patterns: Vec<String> = data.get_patterns().iter().map(|p| p.to_string()).collect();
+
+        let label = if patterns.is_empty() {
+            node.to_string()
+        } else {
+            format!("{}\\n{{{}}}", node, patterns.join(","))
+        };
+
+        dot.push_str(&format!(
+            "    n{} [shape=doublecircle, label=\"{}\"];\n",
+            node, label
+        ));
+    }
+
+    dot.push_str(&format!("    start -> n{};\n", start));
+
+    for node in 0..graph.num_of_nodes() {
+        // (target, is there an empty edge) -> characters, ordered so the output is the same every time
+        let mut edges: BTreeMap<(NodeIndex, bool), Vec<char>> = BTreeMap::new();
+
+        for edge in graph.outgoing_edges(node).unwrap() {
+            let target = graph.traverse(edge).unwrap();
+            let data = graph.get_edge_data(&edge).unwrap();
+
+            // Written like a negated character class, e.g. [^a-c] for anything but a, b or c
+            if let Some(excluded) = data.get_dot_excluded() {
+                dot.push_str(&format!(
+                    "    n{} -> n{} [label=\"[^{}]\"];\n",
+                    node,
+                    target,
+                    format_chars(excluded)
+                ));
+                continue;
+            }
+
+            let label = data.get_dot_label();
+
+            match label {
+                Some(c) => edges.entry((target, false)).or_default().push(c),
+                None => {
+                    edges.entry((target, true)).or_default();
+                }
+            }
+        }
+
+        for ((target, empty), chars) in edges {
+            let label = if empty {
+                "ε".to_string()
+            } else {
+                format_chars(chars)
+            };
+
+            dot.push_str(&format!(
+                "    n{} -> n{} [label=\"{}\"];\n",
+                node, target, label
+            ));
+        }
+    }
+
+    dot.push_str("}\n");
+
+    return dot;
+}
+
+// Collapses runs of 3 or more consecutive characters into ranges, e.g. a,b,c,d,x becomes a-d,x
+fn format_chars(mut chars: Vec<char>) -> String {
+    chars.sort();
+    chars.dedup();
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let mut j = i;
+
+        while j + 1 < chars.len() && chars[j + 1] as u32 == chars[j] as u32 + 1 {
+            j += 1;
+        }
+
+        if j - i >= 2 {
+            parts.push(format!("{}-{}", escape(chars[i]), escape(chars[j])));
+        } else {
+            for c in &chars[i..=j] {
+                parts.push(escape(*c));
+            }
+        }
+
+        i = j + 1;
+    }
+
+    return parts.join(",");
+}
+
+// Makes the character safe to put in a quoted DOT label, and visible if it's whitespace
+fn escape(c: char) -> String {
+    return match c {
+        '"' => "\\\"".to_string(),
+        // Spelled out rather than leaving DOT to treat the backslash as an escape of its own
+        '\\' | '-' | ',' => format!("'{}'", c).replace('\\', "\\\\"),
+        ' ' => "' '".to_string(),
+        c if c.is_control() || c.is_whitespace() => {
+            c.escape_default().to_string().replace('\\', "\\\\")
+        }
+        c => c.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::SizeLimits, nfa::build_nfa, postfixer};
+
+    #[test]
+    fn given_nfa_when_writing_dot_should_draw_empty_edges_and_the_start_and_accepting_states() {
+        let postfix_regex = postfixer::transform("a|b".to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+
+        let dot = to_dot("nfa", &nfa, handle.get_start_state());
+
+        assert_eq!(
+            dot,
+            "\
+digraph nfa {
+    rankdir=LR;
+    start [shape=point];
+    n0 [shape=circle, label=\"0\"];
+    n1 [shape=circle, label=\"1\"];
+    n2 [shape=circle, label=\"2\"];
+    n3 [shape=circle, label=\"3\"];
+    n4 [shape=circle, label=\"4\"];
+    n5 [shape=doublecircle, label=\"5\\n{0}\"];
+    start -> n4;
+    n0 -> n1 [label=\"a\"];
+    n1 -> n5 [label=\"ε\"];
+    n2 -> n3 [label=\"b\"];
+    n3 -> n5 [label=\"ε\"];
+    n4 -> n0 [label=\"ε\"];
+    n4 -> n2 [label=\"ε\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn given_parallel_edges_when_writing_dot_should_merge_them_into_ranges() {
+        let mut graph: Graph<AutomataState, char> = Graph::new();
+
+        let n0 = graph.add_node(AutomataState::new(false));
+        let n1 = graph.add_node(AutomataState::new(true));
+
+        for c in ['d', 'a', 'c', 'b', 'x', '"', 'z', 'y', ' '] {
+            graph.add_edge(n0, n1, c);
+        }
+
+        let dot = to_dot("dfa", &graph, n0);
+
+        assert!(dot.contains("    n0 -> n1 [label=\"' ',\\\",a-d,x-z\"];\n"));
+        assert!(dot.contains("    n1 [shape=doublecircle, label=\"1\"];\n"));
+    }
+
+    #[test]
+    fn given_edge_for_every_other_character_when_writing_dot_should_draw_it_as_a_negated_class() {
+        let postfix_regex = postfixer::transform("~(a|b|c)".to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+
+        let dot = to_dot("nfa", &nfa, handle.get_start_state());
+
+        assert!(dot.contains("[label=\"[^a-c]\"]"));
+    }
+
+    #[test]
+    fn given_characters_that_mean_something_in_a_label_when_formatting_should_escape_them() {
+        assert_eq!(format_chars(vec!['-', ',', '\\']), "',','-','\\\\'");
+        assert_eq!(format_chars(vec!['\n', '\t']), "\\\\t,\\\\n");
+        assert_eq!(format_chars(vec!['é', 'ê', 'ë']), "é-ë");
+    }
+}
@@ -0,0 +1,302 @@
+/* Human readable reports on what the pipeline is doing, for working out why a pattern behaves the way it does.
+ *
+ * explain_compile runs a query through each stage described in the README in turn, and reports what each one
+ * produced along with how long it took.
+ *
+ * trace runs the same unanchored DFA search Regex::is_match does over a line, one character at a time, and
+ * reports every DFA state it goes through along with the NFA states making it up. The NFA states are numbered
+ * the same as in the output of --dump-nfa, so the two can be read side by side.
+ */
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    builder::{BuildError, SizeLimits},
+    dfa::{accepted_patterns, build_dfa_with_limits, delta, empty_closure, DFAState},
+    nfa::{build_multi_nfa, build_nfa, get_nfa_alphabet},
+    postfixer,
+    regex::RegexSymbol,
+};
+
+pub fn explain_compile(query: &str, ignore_case: bool) -> Result<String, String> {
+    let mut report = format!("query    {}\n", query);
+
+    let timer = Instant::now();
+    let tokens = postfixer::tokenize(query.to_string())?;
+    let elapsed = timer.elapsed();
+
+    report.push_str(&stage("tokens", render(tokens.iter()), elapsed));
+
+    let timer = Instant::now();
+    let postfix_regex = postfixer::convert(tokens)?;
+    let elapsed = timer.elapsed();
+
+    report.push_str(&stage("postfix", render(postfix_regex.iter()), elapsed));
+
+    // There's nothing more to build for it, the NFA is just an empty edge
+    if postfix_regex.is_empty() {
+        report.push_str("empty pattern, so it matches the empty string at every position\n");
+        return Ok(report);
+    }
+
+    let limits = SizeLimits::default();
+
+    // Going over a limit is worth reporting rather than failing on, since it's likely why the pattern's slow
+    if let Err(error) = limits.check_nfa(std::slice::from_ref(&postfix_regex)) {
+        report.push_str(&stage("nfa", error.to_string(), Duration::ZERO));
+        return Ok(report);
+    }
+
+    let timer = Instant::now();
+
+    let (handle, nfa) = match build_nfa(postfix_regex, ignore_case, limits) {
+        Ok(built) => built,
+        // & and ~ build DFAs of their own, which can go over the limits too
+        Err(error) => {
+            report.push_str(&stage("nfa", error.to_string(), timer.elapsed()));
+            return Ok(report);
+        }
+    };
+
+    let elapsed = timer.elapsed();
+
+    let nfa_size = format!("{} nodes, {} edges", nfa.num_of_nodes(), nfa.num_of_edges());
+    report.push_str(&stage("nfa", nfa_size, elapsed));
+
+    let timer = Instant::now();
+    let alphabet = get_nfa_alphabet(&nfa);
+    let elapsed = timer.elapsed();
+
+    let symbols: Vec<RegexSymbol> = alphabet.iter().map(|c| RegexSymbol::Char(*c)).collect();
+    report.push_str(&stage("alphabet", render(symbols.iter()), elapsed));
+
+    let timer = Instant::now();
+    let dfa = build_dfa_with_limits(handle, &nfa, alphabet, limits);
+    let elapsed = timer.elapsed();
+
+    let dfa_size = match dfa {
+        Ok((_, dfa)) => format!(
+            "{} states, {} transitions",
+            dfa.num_of_nodes(),
+            dfa.num_of_edges()
+        ),
+        Err(error) => error.to_string(),
+    };
+    report.push_str(&stage("dfa", dfa_size, elapsed));
+
+    return Ok(report);
+}
+
+pub fn trace(
+    postfix_regexes: Vec<VecDeque<RegexSymbol>>,
+    haystack: &str,
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<String, BuildError> {
+    let (handle, nfa) = build_multi_nfa(postfix_regexes, ignore_case, limits)?;
+    let accept = handle.get_accept_state();
+
+    let start_set = empty_closure(&nfa, Rc::new(BTreeSet::from([handle.get_start_state()])));
+
+    // DFA states get numbered in the order the search first reaches them
+    let mut state_ids: HashMap<DFAState, usize> = HashMap::new();
+    let mut report = format!("input    {:?}\n", haystack);
+
+    let describe = |state: &DFAState, state_ids: &mut HashMap<DFAState, usize>| -> String {
+        if let Some(id) = state_ids.get(state) {
+            return format!("D{}", id);
+        }
+
+        let id = state_ids.len();
+        state_ids.insert(state.clone(), id);
+
+        let sub_states: Vec<String> = state.iter().map(|s| s.to_string()).collect();
+
+        return format!("D{} = {{{}}}", id, sub_states.join(", "));
+    };
+
+    let accepting = |state: &DFAState| -> Option<String> {
+        if !state.contains(&accept) {
+            return None;
+        }
+
+        let patterns: Vec<String> = accepted_patterns(&nfa, state)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+
+        return Some(format!("accepting pattern {}", patterns.join(", ")));
+    };
+
+    let mut current = start_set.clone();
+
+    report.push_str(&format!(
+        "start    {}\n",
+        describe(&current, &mut state_ids)
+    ));
+
+    if let Some(accepted) = accepting(&current) {
+        report.push_str(&format!("matched  at byte 0, {}\n", accepted));
+        return Ok(report);
+    }
+
+    for (i, c) in haystack.char_indices() {
+        let moved = delta(&nfa, Rc::new(current.clone()), c);
+        let mut next = empty_closure(&nfa, Rc::new(moved));
+
+        // A new match attempt starts at every position, which is why the start state's set never goes away
+        next.extend(start_set.iter());
+
+        let from = describe(&current, &mut state_ids);
+        let to = describe(&next, &mut state_ids);
+
+        report.push_str(&format!("{:<9}{} -> {}\n", format!("{:?}", c), from, to));
+
+        current = next;
+
+        if let Some(accepted) = accepting(&current) {
+            report.push_str(&format!(
+                "matched  at byte {}, {}\n",
+                i + c.len_utf8(),
+                accepted
+            ));
+            return Ok(report);
+        }
+    }
+
+    report.push_str(&format!(
+        "no match, ran out of input after {} bytes without reaching an accepting state\n",
+        haystack.len()
+    ));
+
+    return Ok(report);
+}
+
+fn stage(name: &str, result: String, elapsed: Duration) -> String {
+    return format!("{:<9}{}  [{:?}]\n", name, result, elapsed);
+}
+
+// Space separated, with characters that would otherwise look like operators or be invisible escaped
+fn render<'a>(symbols: impl Iterator<Item = &'a RegexSymbol>) -> String {
+    let rendered: Vec<String> = symbols
+        .map(|symbol| match symbol {
+            RegexSymbol::Char(c) if "?+*|&~()\\.".contains(*c) => format!("\\{}", c),
+            RegexSymbol::Char(' ') => "' '".to_string(),
+            RegexSymbol::Char(c) if c.is_whitespace() || c.is_control() => {
+                c.escape_default().to_string()
+            }
+            symbol => symbol.to_string(),
+        })
+        .collect();
+
+    return rendered.join(" ");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The timings change from run to run, so leave them off
+    fn without_timings(report: &str) -> Vec<String> {
+        return report
+            .lines()
+            .map(|line| match line.rfind("  [") {
+                Some(i) => line[..i].to_string(),
+                None => line.to_string(),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn given_query_when_explaining_compile_should_report_every_stage() {
+        let report = explain_compile("(you)|(us)", false).unwrap();
+
+        assert_eq!(
+            without_timings(&report),
+            vec![
+                "query    (you)|(us)",
+                "tokens   ( y . o . u ) | ( u . s )",
+                "postfix  y o . u . u s . |",
+                "nfa      12 nodes, 12 edges",
+                "alphabet o s u y",
+                "dfa      6 states, 5 transitions",
+            ]
+        );
+    }
+
+    #[test]
+    fn given_query_with_escaped_operators_when_explaining_compile_should_tell_them_apart() {
+        let report = explain_compile("\\(a b\\)+", true).unwrap();
+        let lines = without_timings(&report);
+
+        assert_eq!(lines[1], "tokens   \\( . a . ' ' . b . \\) +");
+        assert_eq!(lines[4], "alphabet ' ' \\( \\) A B a b");
+    }
+
+    fn trace_query(query: &str, haystack: &str) -> String {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+
+        return trace(
+            vec![postfix_regex],
+            haystack,
+            false,
+            SizeLimits::unlimited(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn given_matching_input_when_tracing_should_show_each_state_until_it_accepts() {
+        assert_eq!(
+            trace_query("ab", "xab!"),
+            "\
+input    \"xab!\"
+start    D0 = {0}
+'x'      D0 -> D0
+'a'      D0 -> D1 = {0, 1, 2}
+'b'      D1 -> D2 = {0, 3}
+matched  at byte 3, accepting pattern 0
+"
+        );
+    }
+
+    #[test]
+    fn given_input_that_doesnt_match_when_tracing_should_say_where_it_ran_out() {
+        let report = trace_query("ab", "aa");
+
+        assert!(report.contains("'a'      D1 -> D1\n"));
+        assert!(report.ends_with(
+            "no match, ran out of input after 2 bytes without reaching an accepting state\n"
+        ));
+    }
+
+    #[test]
+    fn given_empty_pattern_when_tracing_should_match_straight_away() {
+        assert_eq!(
+            trace_query("", "abc"),
+            "\
+input    \"abc\"
+start    D0 = {0, 1}
+matched  at byte 0, accepting pattern 0
+"
+        );
+
+        let report = explain_compile("", false).unwrap();
+
+        assert!(
+            report.ends_with("empty pattern, so it matches the empty string at every position\n")
+        );
+    }
+
+    #[test]
+    fn given_invalid_query_when_explaining_compile_should_return_the_error() {
+        assert_eq!(
+            explain_compile("(a", false).err().unwrap(),
+            "Error - Unbalanced brackets"
+        );
+    }
+}
@@ -0,0 +1,445 @@
+/* The Glushkov construction (or position automaton) builds an NFA with no empty edges between the characters, so
+ * there's far less for dfa::empty_closure to chase than with Thompson's construction. Every character in the
+ * pattern is a position, and there's a state for each position along with a start state. For each sub expression
+ * we work out:
+ *
+ * - nullable: whether it matches the empty string
+ * - first: the positions a match of it can start with
+ * - last: the positions a match of it can end with
+ *
+ * and from those, for every position, the positions that can follow it. Concatenation lets the first of the right
+ * side follow the last of the left side, and * and + let their own first follow their own last. Then there's an
+ * edge from the start state to each first position of the whole pattern, and from each position to the positions
+ * that can follow it, labelled with the character of the position it goes to.
+ *
+ * e.g. for (a|b)*c, with positions a1, b2 and c3, everything follows a1 and b2, and c3 is the only last position:
+ *
+ *      start -a-> a1, start -b-> b2, start -c-> c3
+ *      a1 -a-> a1, a1 -b-> b2, a1 -c-> c3, and the same from b2
+ *
+ * Everything else expects an NFA with one accept state, so the last positions get an empty edge to one. Those are
+ * the only empty edges, and they never lead anywhere else.
+ *
+ * The catch is that the edges can grow with the square of the pattern, e.g. (a|b|c|...)* has an edge between
+ * every pair of positions where Thompson's construction needs just a few per character. estimate_edges works out
+ * an upper bound without building anything, so the two can be chosen between up front.
+ *
+ * & and ~ need the DFAs of their operands, which only Thompson's construction builds, so patterns using them are
+ * always built that way.
+ *
+ * Link: https://en.wikipedia.org/wiki/Glushkov%27s_construction_algorithm
+ */
+
+use std::collections::VecDeque;
+
+use crate::automata::{AutomataComponent, AutomataLabel, AutomataState};
+use crate::case_fold::simple_case_fold_set;
+use crate::graph::Graph;
+use crate::regex::RegexSymbol;
+
+type Position = usize;
+
+struct Fragment {
+    nullable: bool,
+    first: Vec<Position>,
+    last: Vec<Position>,
+}
+
+// The same as Fragment, but only how many first and last positions there are
+struct FragmentSize {
+    nullable: bool,
+    first: usize,
+    last: usize,
+}
+
+// Whether the pattern can be built with the Glushkov construction at all
+pub fn is_supported(postfix_regex: &VecDeque<RegexSymbol>) -> bool {
+    return !postfix_regex
+        .iter()
+        .any(|symbol| matches!(symbol, RegexSymbol::Intersection | RegexSymbol::Complement));
+}
+
+// Adds the position automaton for the pattern to the NFA, which has to be one is_supported accepts
+pub fn compile_pattern(
+    nfa: &mut Graph<AutomataState, AutomataLabel>,
+    postfix_regex: VecDeque<RegexSymbol>,
+    ignore_case: bool,
+) -> AutomataComponent {
+    let mut chars: Vec<char> = Vec::new();
+    let mut follow: Vec<Vec<Position>> = Vec::new();
+    let mut stack: Vec<Fragment> = Vec::new();
+
+    for symbol in postfix_regex {
+        let fragment = match symbol {
+            RegexSymbol::Char(c) => {
+                let position = chars.len();
+
+                chars.push(c);
+                follow.push(Vec::new());
+
+                Fragment {
+                    nullable: false,
+                    first: vec![position],
+                    last: vec![position],
+                }
+            }
+            RegexSymbol::Concat => {
+                let right = stack.pop().unwrap();
+                let mut left = stack.pop().unwrap();
+
+                for position in left.last.iter() {
+                    follow[*position].extend(right.first.iter());
+                }
+
+                if left.nullable {
+                    left.first.extend(right.first.iter());
+                }
+
+                let mut last = right.last;
+
+                if right.nullable {
+                    last.extend(left.last.iter());
+                }
+
+                Fragment {
+                    nullable: left.nullable && right.nullable,
+                    first: left.first,
+                    last,
+                }
+            }
+            RegexSymbol::Alternation => {
+                let right = stack.pop().unwrap();
+                let mut left = stack.pop().unwrap();
+
+                left.first.extend(right.first);
+                left.last.extend(right.last);
+                left.nullable |= right.nullable;
+
+                left
+            }
+            RegexSymbol::Star | RegexSymbol::Plus => {
+                let mut top = stack.pop().unwrap();
+
+                for position in top.last.iter() {
+                    follow[*position].extend(top.first.iter());
+                }
+
+                top.nullable |= symbol == RegexSymbol::Star;
+
+                top
+            }
+            RegexSymbol::Optional => {
+                let mut top = stack.pop().unwrap();
+                top.nullable = true;
+
+                top
+            }
+            _ => panic!("Error - The Glushkov construction only works without parenthesis, & or ~"),
+        };
+
+        stack.push(fragment);
+    }
+
+    let pattern = stack.pop().unwrap();
+
+    let start = nfa.add_node(AutomataState::new(false));
+    let states: Vec<usize> = chars
+        .iter()
+        .map(|_| nfa.add_node(AutomataState::new(false)))
+        .collect();
+    let accept = nfa.add_node(AutomataState::new(false));
+
+    let add_edges = |nfa: &mut Graph<AutomataState, AutomataLabel>, from, to: Position| {
+        if ignore_case {
+            for folded in simple_case_fold_set(chars[to]) {
+                nfa.add_edge(from, states[to], AutomataLabel::new(Some(folded)));
+            }
+        } else {
+            nfa.add_edge(from, states[to], AutomataLabel::new(Some(chars[to])));
+        }
+    };
+
+    for position in dedup(pattern.first) {
+        add_edges(nfa, start, position);
+    }
+
+    // (a*)* and the like add the same positions to follow more than once
+    for (position, next) in follow.into_iter().enumerate() {
+        for to in dedup(next) {
+            add_edges(nfa, states[position], to);
+        }
+    }
+
+    for position in dedup(pattern.last) {
+        nfa.add_edge(states[position], accept, AutomataLabel::new(None));
+    }
+
+    if pattern.nullable {
+        nfa.add_edge(start, accept, AutomataLabel::new(None));
+    }
+
+    return AutomataComponent::new(start, accept);
+}
+
+// An upper bound on how many edges compile_pattern adds for the pattern, worked out from how many first and last
+// positions each sub expression has without building anything. Ignoring case, every edge into a position is added
+// once for each character its character folds to, so first counts those rather than the positions.
+pub fn estimate_edges(postfix_regex: &VecDeque<RegexSymbol>, ignore_case: bool) -> usize {
+    let mut stack: Vec<FragmentSize> = Vec::new();
+    let mut edges: usize = 0;
+
+    for symbol in postfix_regex {
+        let size = match symbol {
+            RegexSymbol::Char(c) => FragmentSize {
+                nullable: false,
+                first: if ignore_case {
+                    simple_case_fold_set(*c).len()
+                } else {
+                    1
+                },
+                last: 1,
+            },
+            RegexSymbol::Concat => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+
+                edges = edges.saturating_add(left.last.saturating_mul(right.first));
+
+                FragmentSize {
+                    nullable: left.nullable && right.nullable,
+                    first: left.first + if left.nullable { right.first } else { 0 },
+                    last: right.last + if right.nullable { left.last } else { 0 },
+                }
+            }
+            RegexSymbol::Alternation => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+
+                FragmentSize {
+                    nullable: left.nullable || right.nullable,
+                    first: left.first + right.first,
+                    last: left.last + right.last,
+                }
+            }
+            RegexSymbol::Star | RegexSymbol::Plus => {
+                let top = stack.pop().unwrap();
+
+                edges = edges.saturating_add(top.last.saturating_mul(top.first));
+
+                FragmentSize {
+                    nullable: top.nullable || *symbol == RegexSymbol::Star,
+                    ..top
+                }
+            }
+            RegexSymbol::Optional => FragmentSize {
+                nullable: true,
+                ..stack.pop().unwrap()
+            },
+            _ => return usize::MAX,
+        };
+
+        stack.push(size);
+    }
+
+    return match stack.pop() {
+        Some(pattern) => edges + pattern.first + pattern.last + 1,
+        None => 0,
+    };
+}
+
+fn dedup(mut positions: Vec<Position>) -> Vec<Position> {
+    positions.sort_unstable();
+    positions.dedup();
+
+    return positions;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::SizeLimits, complete_dfa::CompleteDfa, dfa::empty_closure, nfa::build_nfa,
+        postfixer,
+    };
+    use std::{collections::BTreeSet, rc::Rc};
+
+    fn build(
+        query: &str,
+        ignore_case: bool,
+    ) -> (AutomataComponent, Graph<AutomataState, AutomataLabel>) {
+        let mut nfa: Graph<AutomataState, AutomataLabel> = Graph::new();
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let handle = compile_pattern(&mut nfa, postfix_regex, ignore_case);
+
+        nfa.get_node_data_mut(&handle.get_accept_state())
+            .unwrap()
+            .mark_as_accepting_pattern(0);
+
+        return (handle, nfa);
+    }
+
+    #[test]
+    fn given_pattern_when_building_position_automaton_should_have_a_state_per_character_and_edges_between_them(
+    ) {
+        let (handle, nfa) = build("(a|b)*c", false);
+
+        // The start, a, b, c and the accept state
+        assert_eq!(nfa.num_of_nodes(), 5);
+        // 3 from the start, 3 from each of a and b, and the empty edge from c
+        assert_eq!(nfa.num_of_edges(), 10);
+
+        // Only the accept state is an empty edge away from anything
+        let closure = empty_closure(&nfa, Rc::new(BTreeSet::from([handle.get_start_state()])));
+        assert_eq!(closure.len(), 1);
+    }
+
+    #[test]
+    fn given_patterns_when_building_with_glushkov_should_accept_the_same_language_as_thompson() {
+        let queries = [
+            "(a|b)*abb",
+            "a*b*",
+            "(ab)+c?",
+            "((a*)*|b)+",
+            "(you)|(us)|them",
+            "x(y|z?)*",
+        ];
+
+        for query in queries {
+            let (handle, nfa) = build(query, false);
+            let glushkov = CompleteDfa::from_nfa(&handle, &nfa, SizeLimits::unlimited()).unwrap();
+
+            let (thompson_handle, thompson) = build_nfa(
+                postfixer::transform(query.to_string()).unwrap(),
+                false,
+                SizeLimits::unlimited(),
+            )
+            .unwrap();
+            let thompson =
+                CompleteDfa::from_nfa(&thompson_handle, &thompson, SizeLimits::unlimited())
+                    .unwrap();
+
+            assert_eq!(glushkov.find_difference(&thompson), None, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_ignore_case_when_building_position_automaton_should_add_an_edge_for_each_case() {
+        let (_, nfa) = build("k", true);
+
+        // k, K and the Kelvin sign, then the empty edge to the accept state
+        assert_eq!(nfa.num_of_edges(), 4);
+    }
+
+    #[test]
+    fn given_patterns_when_estimating_edges_should_never_be_less_than_what_gets_built() {
+        for query in [
+            "(a|b)*abb",
+            "(a|b|c|d|e)*",
+            "abc",
+            "((a*)*|b)+",
+            "(x?y?)*z",
+            "(k|s)*",
+        ] {
+            for ignore_case in [false, true] {
+                let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+                let estimate = estimate_edges(&postfix_regex, ignore_case);
+                let (_, nfa) = build(query, ignore_case);
+
+                assert!(estimate >= nfa.num_of_edges(), "{} {}", query, ignore_case);
+            }
+        }
+    }
+
+    #[test]
+    fn given_patterns_with_intersection_or_complement_when_checking_should_not_support_them() {
+        let supported =
+            |query: &str| is_supported(&postfixer::transform(query.to_string()).unwrap());
+
+        assert!(supported("(a|b)*c"));
+        assert!(!supported("a&b"));
+        assert!(!supported("x~(y)"));
+    }
+
+    // Backs up the numbers behind GLUSHKOV_EDGE_LIMIT. It takes a while, so it only runs when asked for with
+    // cargo test --release -- --ignored --nocapture glushkov
+    #[test]
+    #[ignore]
+    fn benchmark_glushkov_against_thompson() {
+        use crate::{
+            builder::RegexBuilder,
+            dfa::build_dfa,
+            nfa::{build_multi_nfa_with, NfaConstruction},
+            regex::get_alphabet,
+        };
+        use std::time::{Duration, Instant};
+
+        fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+            let runs = 3;
+            let started = Instant::now();
+
+            for _ in 0..runs {
+                std::hint::black_box(f());
+            }
+
+            return started.elapsed() / runs;
+        }
+
+        let words = |n: usize| {
+            (0..n)
+                .map(|i| format!("w{}", i))
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+        let queries = [
+            ("500 word alternation", words(500)),
+            ("(a|b)*a(a|b)x12", format!("(a|b)*a{}", "(a|b)".repeat(12))),
+            ("nested stars", "((a*b*)*(c|d)*)*e((f*g)*h)*".to_string()),
+            ("(w1|...|w2000)*x", format!("({})*x", words(2000))),
+        ];
+        let haystack = "w12 abababbabaab cdcdaabe fgfgh w1999x ".repeat(20_000);
+
+        for (name, query) in queries.iter() {
+            let mut edges = Vec::new();
+            let mut building = Vec::new();
+            let mut searching = Vec::new();
+
+            for construction in [NfaConstruction::Thompson, NfaConstruction::Glushkov] {
+                let build = || {
+                    let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+                    let alphabet = get_alphabet(&postfix_regex);
+                    let (handle, nfa) = build_multi_nfa_with(
+                        vec![postfix_regex],
+                        false,
+                        construction,
+                        SizeLimits::unlimited(),
+                    )
+                    .unwrap();
+
+                    return (nfa.num_of_edges(), build_dfa(handle, &nfa, alphabet));
+                };
+
+                edges.push(build().0);
+                building.push(time(build));
+
+                let regex = RegexBuilder::new()
+                    .nfa_construction(construction)
+                    .build(query)
+                    .unwrap();
+                searching.push(time(|| regex.find_iter(&haystack).count()));
+            }
+
+            println!(
+                "{}: {} edges against {}, building the DFA {:?} against {:?} ({:.1}x), searching {:?} against {:?} ({:.1}x)",
+                name,
+                edges[1],
+                edges[0],
+                building[1],
+                building[0],
+                building[0].as_secs_f64() / building[1].as_secs_f64(),
+                searching[1],
+                searching[0],
+                searching[0].as_secs_f64() / searching[1].as_secs_f64(),
+            );
+        }
+    }
+}
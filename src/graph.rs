@@ -1,7 +1,6 @@
 // https://smallcultfollowing.com/babysteps/blog/2015/04/06/modeling-graphs-in-rust-using-vector-indices/
-use std::cell::RefCell;
-use std::rc::Rc;
-
+// Node and edge data is stored inline rather than behind shared pointers, so a finished graph is plain data that
+// can be shared between threads.
 pub struct Graph<T, U> {
     nodes: Vec<Node<T>>,
     edges: Vec<Edge<U>>,
@@ -11,7 +10,7 @@ pub type NodeIndex = usize;
 
 pub struct Node<T> {
     first_outgoing_edge: Option<EdgeIndex>,
-    data: Rc<RefCell<T>>,
+    data: T,
 }
 
 pub type EdgeIndex = usize;
@@ -19,7 +18,7 @@ pub type EdgeIndex = usize;
 pub struct Edge<U> {
     target: NodeIndex,
     next_outgoing_edge: Option<EdgeIndex>,
-    data: Rc<RefCell<U>>,
+    data: U,
 }
 
 impl<T, U> Graph<T, U> {
@@ -56,17 +55,14 @@ impl<T, U> Graph<T, U> {
 
         let first_outgoing_edge = self.nodes[source].first_outgoing_edge;
 
-        match first_outgoing_edge {
-            Some(edge) => {
-                edges.push(edge);
+        if let Some(edge) = first_outgoing_edge {
+            edges.push(edge);
 
-                let mut prev = edge;
-                while let Some(next) = self.edges[prev].next_outgoing_edge {
-                    edges.push(next);
-                    prev = next;
-                }
+            let mut prev = edge;
+            while let Some(next) = self.edges[prev].next_outgoing_edge {
+                edges.push(next);
+                prev = next;
             }
-            None => (),
         }
 
         return Ok(edges);
@@ -84,8 +80,7 @@ impl<T, U> Graph<T, U> {
         if index > end {
             return Err(format!(
                 "Error - Invalid source node index: {}, must be between 0-{}",
-                index.to_string(),
-                end.to_string()
+                index, end
             ));
         }
 
@@ -98,24 +93,29 @@ impl<T, U> Graph<T, U> {
         if index > end {
             return Err(format!(
                 "Error - Invalid edge index: {}, must be between 0-{}",
-                index.to_string(),
-                end.to_string()
+                index, end
             ));
         }
 
         return Ok(index);
     }
 
-    pub fn get_node_data(&self, index: &NodeIndex) -> Result<Rc<RefCell<T>>, String> {
+    pub fn get_node_data(&self, index: &NodeIndex) -> Result<&T, String> {
+        let index = self.check_node_index(*index)?;
+
+        return Ok(&self.nodes[index].data);
+    }
+
+    pub fn get_node_data_mut(&mut self, index: &NodeIndex) -> Result<&mut T, String> {
         let index = self.check_node_index(*index)?;
 
-        return Ok(self.nodes[index].data.clone());
+        return Ok(&mut self.nodes[index].data);
     }
 
-    pub fn get_edge_data(&self, index: &EdgeIndex) -> Result<Rc<RefCell<U>>, String> {
+    pub fn get_edge_data(&self, index: &EdgeIndex) -> Result<&U, String> {
         let index = self.check_edge_index(*index)?;
 
-        return Ok(self.edges[index].data.clone());
+        return Ok(&self.edges[index].data);
     }
 
     pub fn num_of_nodes(&self) -> usize {
@@ -127,11 +127,17 @@ impl<T, U> Graph<T, U> {
     }
 }
 
+impl<T, U> Default for Graph<T, U> {
+    fn default() -> Graph<T, U> {
+        return Graph::new();
+    }
+}
+
 impl<T> Node<T> {
     pub fn new(first_outgoing_edge: Option<EdgeIndex>, data: T) -> Node<T> {
         return Node {
             first_outgoing_edge,
-            data: Rc::new(RefCell::new(data)),
+            data,
         };
     }
 }
@@ -141,11 +147,12 @@ impl<U> Edge<U> {
         return Edge {
             target,
             next_outgoing_edge,
-            data: Rc::new(RefCell::new(data)),
+            data,
         };
     }
 }
 
+#[cfg(test)]
 mod test {
     use super::*;
 
@@ -174,22 +181,22 @@ mod test {
         let n0_outgoing_edges = graph.outgoing_edges(n0).unwrap();
 
         assert!(n0_outgoing_edges.len() == 2);
-        assert!(*graph.get_edge_data(&n0_outgoing_edges[0]).unwrap().borrow() == "n0->n3");
-        assert!(*graph.get_edge_data(&n0_outgoing_edges[1]).unwrap().borrow() == "n0->n1");
+        assert!(*graph.get_edge_data(&n0_outgoing_edges[0]).unwrap() == "n0->n3");
+        assert!(*graph.get_edge_data(&n0_outgoing_edges[1]).unwrap() == "n0->n1");
 
         let n1_outgoing_edges = graph.outgoing_edges(n1).unwrap();
 
         assert!(n1_outgoing_edges.len() == 1);
-        assert!(*graph.get_edge_data(&n1_outgoing_edges[0]).unwrap().borrow() == "n1->n2");
+        assert!(*graph.get_edge_data(&n1_outgoing_edges[0]).unwrap() == "n1->n2");
 
         let n2_outgoing_edges = graph.outgoing_edges(n2).unwrap();
 
-        assert!(n2_outgoing_edges.len() == 0);
+        assert!(n2_outgoing_edges.is_empty());
 
         let n3_outgoing_edges = graph.outgoing_edges(n3).unwrap();
 
         assert!(n3_outgoing_edges.len() == 1);
-        assert!(*graph.get_edge_data(&n3_outgoing_edges[0]).unwrap().borrow() == "n3->n2");
+        assert!(*graph.get_edge_data(&n3_outgoing_edges[0]).unwrap() == "n3->n2");
     }
 
     #[test]
@@ -216,18 +223,18 @@ mod test {
         assert!(n0_outgoing_edges.len() == 1);
 
         let first_jump = graph.traverse(n0_outgoing_edges[0]).unwrap();
-        assert!(*graph.get_node_data(&first_jump).unwrap().borrow() == "n1");
+        assert!(*graph.get_node_data(&first_jump).unwrap() == "n1");
 
         let n1_outgoing_edges = graph.outgoing_edges(first_jump).unwrap();
         assert!(n1_outgoing_edges.len() == 1);
 
         let second_jump = graph.traverse(n1_outgoing_edges[0]).unwrap();
-        assert!(*graph.get_node_data(&second_jump).unwrap().borrow() == "n2");
+        assert!(*graph.get_node_data(&second_jump).unwrap() == "n2");
 
         let n2_outgoing_edges = graph.outgoing_edges(second_jump).unwrap();
         assert!(n2_outgoing_edges.len() == 1);
 
         let third_jump = graph.traverse(n2_outgoing_edges[0]).unwrap();
-        assert!(*graph.get_node_data(&third_jump).unwrap().borrow() == "n0");
+        assert!(*graph.get_node_data(&third_jump).unwrap() == "n0");
     }
 }
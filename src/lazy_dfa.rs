@@ -0,0 +1,603 @@
+/* The powerset construction in dfa.rs determinizes the whole NFA up front, which is exponential
+ * in the worst case, e.g. (a|b)*a(a|b)(a|b)...(a|b) needs a DFA state for every combination of the
+ * last n characters seen. Most of those states are never reached by any real input though.
+ *
+ * The lazy DFA instead only computes the states (with the same dfa::Powerset the eager construction uses, so each
+ * NFA node's empty closure is only chased once and each set of NFA nodes is interned as an integer id) at the point
+ * the input actually reaches them, and caches them along with their transitions so that the common paths through
+ * the automaton run at full DFA speed. Each cached state keeps
+ * a row of transitions indexed by char class (see char_classes.rs), so every char in a class shares one entry.
+ *
+ * The cache has a memory cap, which the Powerset's closures and sets count towards too, with at most half of it
+ * going to closures. Once adding another state would take it over the cap the whole cache, and the sets the states
+ * were made of, is flushed and rebuilt starting from the state we're moving into. So in the worst case we degrade to simulating the
+ * NFA one step at a time, but we never use more than a bounded amount of memory doing so. When that keeps
+ * happening the DFA is effectively bigger than the budget, so callers can ask the search to give up instead
+ * and fall back to an engine that doesn't need to determinize at all (see pikevm.rs).
+ *
+ * Unanchored searches work by adding the empty closure of the NFA start state into every DFA state, which is
+ * equivalent to starting a new attempt at the match from every position in the input. Just knowing that some
+ * attempt matched loses where it started though, so to find the leftmost-longest match the attempts are kept
+ * in groups ordered by where they started (the same trick RE2 uses). Once a group accepts, every group after it
+ * started further right and can be thrown away, so the last position we see an accepting state is the end of
+ * the leftmost-longest match. The start is then found by running an anchored DFA built from the reversed NFA
+ * backwards from that end.
+ */
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    mem,
+    sync::Arc,
+};
+
+use crate::{
+    automata::{AutomataComponent, AutomataLabel, AutomataState, PatternID},
+    char_classes::CharClasses,
+    dfa::{accepted_patterns, Powerset, StateSetId},
+    graph::{Graph, NodeIndex},
+    nfa::get_nfa_alphabet,
+};
+
+// Roughly in line with what other regex engines give their lazy DFA by default
+pub const DEFAULT_CACHE_CAPACITY: usize = 2 * (1 << 20);
+
+// How many times a single search may flush the cache before the try_* searches give up on it
+pub const MAX_CACHE_FLUSHES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    // Unanchored, only cares whether there's a match at all so every attempt can be merged together
+    Earliest,
+    // Unanchored, keeps attempts grouped by where they started so the leftmost-longest match can be found
+    LeftmostLongest,
+    // Only matches starting right at the beginning of the input
+    Anchored,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CacheExhausted;
+
+pub(crate) type StateId = usize;
+
+// The groups are ids from the lazy DFA's Powerset, so they're only good until the next flush
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct StateKey {
+    groups: Vec<StateSetId>,
+    // Whether new match attempts still get started at each position
+    seeding: bool,
+}
+
+#[derive(Clone)]
+struct CachedState {
+    key: Arc<StateKey>,
+    accepting: bool,
+    patterns: BTreeSet<PatternID>,
+    // Indexed by the class of the char, since every char in a class goes to the same state
+    transitions: Vec<StateId>,
+}
+
+// A transition that hasn't been worked out yet
+const UNKNOWN: StateId = usize::MAX;
+
+#[derive(Clone)]
+pub struct LazyDfa {
+    nfa: Arc<Graph<AutomataState, AutomataLabel>>,
+    accept: NodeIndex,
+    classes: CharClasses,
+    kind: SearchKind,
+    // The empty closure of the NFA start state, kept as nodes rather than an id since a flush forgets every id
+    start_set: Vec<NodeIndex>,
+    powerset: Powerset,
+    num_of_patterns: usize,
+    states: Vec<CachedState>,
+    state_ids: HashMap<Arc<StateKey>, StateId>,
+    cache_capacity: usize,
+    memory_usage: usize,
+    cache_flushes: usize,
+    // Going over either of these flushes the cache, the same as going over its capacity does
+    max_states: usize,
+    max_transitions: usize,
+    num_of_transitions: usize,
+}
+
+impl LazyDfa {
+    pub fn new(
+        handle: AutomataComponent,
+        nfa: Arc<Graph<AutomataState, AutomataLabel>>,
+        kind: SearchKind,
+        cache_capacity: usize,
+    ) -> LazyDfa {
+        let all_states: Vec<NodeIndex> = (0..nfa.num_of_nodes()).collect();
+        let num_of_patterns = accepted_patterns(&nfa, &all_states).len();
+        let classes = CharClasses::new(&nfa, &get_nfa_alphabet(&nfa));
+
+        let mut powerset =
+            Powerset::new(&nfa, &classes.representatives()).with_closure_budget(cache_capacity / 2);
+        let start = powerset.closure(&[handle.get_start_state()]);
+        let start_set = powerset.get_set(start).to_vec();
+
+        // Nothing's been cached yet, so there's nothing to count the start set against
+        powerset.clear_sets();
+
+        return LazyDfa {
+            nfa,
+            accept: handle.get_accept_state(),
+            classes,
+            kind,
+            start_set,
+            powerset,
+            num_of_patterns,
+            states: Vec::new(),
+            state_ids: HashMap::new(),
+            cache_capacity,
+            memory_usage: 0,
+            cache_flushes: 0,
+            max_states: usize::MAX,
+            max_transitions: usize::MAX,
+            num_of_transitions: 0,
+        };
+    }
+
+    pub fn with_limits(mut self, max_states: usize, max_transitions: usize) -> LazyDfa {
+        self.max_states = max_states;
+        self.max_transitions = max_transitions;
+        return self;
+    }
+
+    pub fn is_match(&mut self, haystack: &str) -> bool {
+        return self.try_is_match_with_limit(haystack, None).unwrap();
+    }
+
+    // Gives up if the search had to flush the cache more than MAX_CACHE_FLUSHES times, i.e. the part
+    // of the DFA this input needs doesn't fit in the cache so we're better off not building it.
+    pub fn try_is_match(&mut self, haystack: &str) -> Result<bool, CacheExhausted> {
+        return self.try_is_match_with_limit(haystack, Some(MAX_CACHE_FLUSHES));
+    }
+
+    // Returns the position of the last accepting state seen while scanning forwards until the automaton dies.
+    // For SearchKind::LeftmostLongest that's the end of the leftmost-longest match.
+    pub fn find_end(&mut self, haystack: &str) -> Option<usize> {
+        return self.try_find_end_with_limit(haystack, None).unwrap();
+    }
+
+    pub fn try_find_end(&mut self, haystack: &str) -> Result<Option<usize>, CacheExhausted> {
+        return self.try_find_end_with_limit(haystack, Some(MAX_CACHE_FLUSHES));
+    }
+
+    // Scans backwards from the end of the haystack, so this expects to be built from a reversed NFA.
+    // With SearchKind::Anchored the result is the furthest left a match ending at haystack.len() can start.
+    pub fn rfind_start(&mut self, haystack: &str) -> Option<usize> {
+        return self.try_rfind_start_with_limit(haystack, None).unwrap();
+    }
+
+    pub fn try_rfind_start(&mut self, haystack: &str) -> Result<Option<usize>, CacheExhausted> {
+        return self.try_rfind_start_with_limit(haystack, Some(MAX_CACHE_FLUSHES));
+    }
+
+    // Returns every pattern with a match somewhere in the haystack, for a DFA built from nfa::build_multi_nfa.
+    // Expects SearchKind::Earliest, since we need to keep seeing every match attempt through to the end.
+    pub fn matching_patterns(&mut self, haystack: &str) -> BTreeSet<PatternID> {
+        return self
+            .try_matching_patterns_with_limit(haystack, None)
+            .unwrap();
+    }
+
+    pub fn try_matching_patterns(
+        &mut self,
+        haystack: &str,
+    ) -> Result<BTreeSet<PatternID>, CacheExhausted> {
+        return self.try_matching_patterns_with_limit(haystack, Some(MAX_CACHE_FLUSHES));
+    }
+
+    pub fn num_of_cached_states(&self) -> usize {
+        return self.states.len();
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        return self.memory_usage + self.powerset.memory_usage();
+    }
+
+    pub fn cache_flushes(&self) -> usize {
+        return self.cache_flushes;
+    }
+
+    fn try_is_match_with_limit(
+        &mut self,
+        haystack: &str,
+        flush_limit: Option<usize>,
+    ) -> Result<bool, CacheExhausted> {
+        let chars = haystack.char_indices().map(|(i, c)| (i + c.len_utf8(), c));
+
+        let result = self.scan(0, chars, true, flush_limit)?;
+
+        return Ok(result.is_some());
+    }
+
+    fn try_find_end_with_limit(
+        &mut self,
+        haystack: &str,
+        flush_limit: Option<usize>,
+    ) -> Result<Option<usize>, CacheExhausted> {
+        let chars = haystack.char_indices().map(|(i, c)| (i + c.len_utf8(), c));
+
+        return self.scan(0, chars, false, flush_limit);
+    }
+
+    fn try_rfind_start_with_limit(
+        &mut self,
+        haystack: &str,
+        flush_limit: Option<usize>,
+    ) -> Result<Option<usize>, CacheExhausted> {
+        let chars = haystack.char_indices().rev();
+
+        return self.scan(haystack.len(), chars, false, flush_limit);
+    }
+
+    fn try_matching_patterns_with_limit(
+        &mut self,
+        haystack: &str,
+        flush_limit: Option<usize>,
+    ) -> Result<BTreeSet<PatternID>, CacheExhausted> {
+        let flushes_before = self.cache_flushes;
+        let mut current = self.start_state();
+        let mut found: BTreeSet<PatternID> = self.states[current].patterns.clone();
+
+        for c in haystack.chars() {
+            // No point carrying on once every pattern has matched
+            if found.len() == self.num_of_patterns || self.is_dead(current) {
+                break;
+            }
+
+            current = self.next_state(current, c);
+            found.extend(self.states[current].patterns.iter());
+
+            if let Some(limit) = flush_limit {
+                if self.cache_flushes - flushes_before > limit {
+                    return Err(CacheExhausted);
+                }
+            }
+        }
+
+        return Ok(found);
+    }
+
+    // Runs the automaton over (position after consuming the char, char) pairs, returning the position
+    // of the last accepting state seen, or the first one if we only care whether there's a match at all.
+    fn scan(
+        &mut self,
+        initial_position: usize,
+        chars: impl Iterator<Item = (usize, char)>,
+        stop_at_first_match: bool,
+        flush_limit: Option<usize>,
+    ) -> Result<Option<usize>, CacheExhausted> {
+        let flushes_before = self.cache_flushes;
+        let mut current = self.start_state();
+        let mut last_match: Option<usize> = None;
+
+        if self.states[current].accepting {
+            last_match = Some(initial_position);
+
+            if stop_at_first_match {
+                return Ok(last_match);
+            }
+        }
+
+        for (position, c) in chars {
+            if self.is_dead(current) {
+                break;
+            }
+
+            current = self.next_state(current, c);
+
+            if self.states[current].accepting {
+                last_match = Some(position);
+
+                if stop_at_first_match {
+                    break;
+                }
+            }
+
+            if let Some(limit) = flush_limit {
+                if self.cache_flushes - flushes_before > limit {
+                    return Err(CacheExhausted);
+                }
+            }
+        }
+
+        return Ok(last_match);
+    }
+
+    // Stepping through the input a char at a time with start_state and next_state is for when it doesn't arrive
+    // all at once. A state is only good until the next call to either, since a flush throws away every other one.
+    pub(crate) fn is_accepting(&self, state: StateId) -> bool {
+        return self.states[state].accepting;
+    }
+
+    fn is_dead(&self, state: StateId) -> bool {
+        let key = &self.states[state].key;
+
+        return key.groups.is_empty() && !key.seeding;
+    }
+
+    pub(crate) fn start_state(&mut self) -> StateId {
+        let start = self.powerset.intern(&self.start_set);
+        let key = self.finish_key(vec![start], self.kind != SearchKind::Anchored);
+
+        return self.add_state(Arc::new(key), false);
+    }
+
+    pub(crate) fn next_state(&mut self, current: StateId, c: char) -> StateId {
+        let class = self.classes.get_class(c);
+        let next = self.states[current].transitions[class];
+
+        if next != UNKNOWN {
+            return next;
+        }
+
+        // Any char in the class would do, so use the same one every time
+        let representative = self.classes.get_members(class)[0];
+        let next_key = self.step(&self.states[current].key.clone(), representative);
+
+        let flushes_before = self.cache_flushes;
+        let too_many_transitions = self.num_of_transitions >= self.max_transitions;
+        let next = self.add_state(Arc::new(next_key), too_many_transitions);
+
+        // If the cache got flushed making room for the next state then the current state is gone,
+        // so there's nothing left to hang the transition off
+        if self.cache_flushes == flushes_before {
+            self.states[current].transitions[class] = next;
+            self.num_of_transitions += 1;
+        }
+
+        return next;
+    }
+
+    fn step(&mut self, from: &StateKey, c: char) -> StateKey {
+        // Restart the match attempt at the next position
+        let restart: &[NodeIndex] = if from.seeding { &self.start_set } else { &[] };
+
+        // Only the leftmost-longest search needs to know where each attempt started
+        let merge = self.kind != SearchKind::LeftmostLongest;
+        let groups = self.powerset.step_groups(&from.groups, c, restart, merge);
+
+        return self.finish_key(groups, from.seeding);
+    }
+
+    fn finish_key(&self, mut groups: Vec<StateSetId>, mut seeding: bool) -> StateKey {
+        if self.kind == SearchKind::LeftmostLongest {
+            if let Some(i) = groups.iter().position(|g| self.has_accept(*g)) {
+                // Every group after the accepting one started further right, so it can't win
+                groups.truncate(i + 1);
+                seeding = false;
+            }
+        }
+
+        return StateKey { groups, seeding };
+    }
+
+    fn has_accept(&self, group: StateSetId) -> bool {
+        return self
+            .powerset
+            .get_set(group)
+            .binary_search(&self.accept)
+            .is_ok();
+    }
+
+    // Adds the state unless it's already cached. Going over the transition limit flushes the cache, so that's left
+    // to here too, since the key has to make it through the flush.
+    fn add_state(&mut self, mut key: Arc<StateKey>, too_many_transitions: bool) -> StateId {
+        if !too_many_transitions {
+            if let Some(id) = self.state_ids.get(&key) {
+                return *id;
+            }
+        }
+
+        let cost = mem::size_of::<CachedState>()
+            + mem::size_of::<(Arc<StateKey>, StateId)>()
+            + key.groups.len() * mem::size_of::<StateSetId>()
+            + self.classes.num_of_classes() * mem::size_of::<StateId>();
+
+        // Always allow at least one state in the cache, otherwise we couldn't make any progress
+        if too_many_transitions
+            || !self.states.is_empty()
+                && (self.memory_usage + self.powerset.memory_usage() + cost > self.cache_capacity
+                    || self.states.len() >= self.max_states)
+        {
+            key = self.flush(&key);
+        }
+
+        let id = self.states.len();
+        let accepting = key.groups.iter().any(|g| self.has_accept(*g));
+
+        // Only states the accept state is in can have any patterns to report
+        let patterns = if accepting {
+            key.groups
+                .iter()
+                .flat_map(|g| accepted_patterns(&self.nfa, self.powerset.get_set(*g)))
+                .collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        self.states.push(CachedState {
+            key: key.clone(),
+            accepting,
+            patterns,
+            transitions: vec![UNKNOWN; self.classes.num_of_classes()],
+        });
+        self.state_ids.insert(key, id);
+        self.memory_usage += cost;
+
+        return id;
+    }
+
+    // Throws away every cached state and the Powerset's sets with them, so the key of the state about to be added
+    // gets its sets interned again
+    fn flush(&mut self, pending: &StateKey) -> Arc<StateKey> {
+        let groups: Vec<Vec<NodeIndex>> = pending
+            .groups
+            .iter()
+            .map(|g| self.powerset.get_set(*g).to_vec())
+            .collect();
+
+        self.states.clear();
+        self.state_ids.clear();
+        self.powerset.clear_sets();
+        self.memory_usage = 0;
+        self.num_of_transitions = 0;
+        self.cache_flushes += 1;
+
+        return Arc::new(StateKey {
+            groups: groups.iter().map(|g| self.powerset.intern(g)).collect(),
+            seeding: pending.seeding,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::SizeLimits,
+        nfa::{build_nfa, reverse_nfa},
+        postfixer,
+    };
+
+    fn build(query: &str, kind: SearchKind, cache_capacity: usize) -> LazyDfa {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+
+        return LazyDfa::new(handle, Arc::new(nfa), kind, cache_capacity);
+    }
+
+    fn build_reversed(query: &str) -> LazyDfa {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+        let (handle, nfa) = reverse_nfa(&handle, &nfa);
+
+        return LazyDfa::new(
+            handle,
+            Arc::new(nfa),
+            SearchKind::Anchored,
+            DEFAULT_CACHE_CAPACITY,
+        );
+    }
+
+    #[test]
+    fn given_basic_patterns_when_matching_should_find_them_anywhere_in_the_haystack() {
+        let mut lazy = build("(you)|(us)", SearchKind::Earliest, DEFAULT_CACHE_CAPACITY);
+
+        assert!(lazy.is_match("I'm nobody! Who are you?"));
+        assert!(lazy.is_match("They'd banish us, you know."));
+        assert!(!lazy.is_match("How dreary to be somebody!"));
+    }
+
+    #[test]
+    fn given_pattern_with_exponential_dfa_when_matching_should_only_build_the_states_it_visits() {
+        let query = format!("(a|b)*a{}", "(a|b)".repeat(20));
+        let mut lazy = build(&query, SearchKind::Earliest, DEFAULT_CACHE_CAPACITY);
+
+        let haystack = format!("a{}", "b".repeat(20));
+
+        assert!(lazy.is_match(&haystack));
+        assert!(!lazy.is_match(&"b".repeat(40)));
+        assert!(lazy.num_of_cached_states() <= 2 * haystack.len() + 2);
+    }
+
+    #[test]
+    fn given_large_alternation_when_matching_should_keep_closures_and_sets_within_the_cache_capacity(
+    ) {
+        let words: Vec<String> = (0..100).map(|i| format!("word{}x", i)).collect();
+        let capacity = 16 * 1024;
+        let mut lazy = build(&words.join("|"), SearchKind::Earliest, capacity);
+
+        for i in 0..100 {
+            assert!(lazy.is_match(&format!("a word{}x b", i)));
+            assert!(lazy.memory_usage() <= capacity);
+        }
+
+        assert!(!lazy.is_match("word100x word10 wordx"));
+        assert!(lazy.cache_flushes() > 0);
+    }
+
+    #[test]
+    fn given_tiny_cache_capacity_when_matching_should_flush_and_still_return_correct_answers() {
+        let query = format!("(a|b)*a{}", "(a|b)".repeat(10));
+        let mut lazy = build(&query, SearchKind::Earliest, 1024);
+
+        let haystack = "abbabaababbbabaababaabbbabbbbbbbbbb";
+
+        assert!(lazy.is_match(haystack));
+        assert!(!lazy.is_match(&"b".repeat(40)));
+        assert!(lazy.cache_flushes() > 0);
+    }
+
+    #[test]
+    fn given_state_and_transition_limits_when_matching_should_never_cache_more_than_them() {
+        let query = format!("(a|b)*a{}", "(a|b)".repeat(10));
+        let haystack = "abbabaababbbabaababaabbbabbbbbbbbbb";
+
+        let mut lazy =
+            build(&query, SearchKind::Earliest, DEFAULT_CACHE_CAPACITY).with_limits(8, usize::MAX);
+
+        assert!(lazy.is_match(haystack));
+        assert!(lazy.num_of_cached_states() <= 8);
+        assert!(lazy.cache_flushes() > 0);
+
+        let mut lazy =
+            build(&query, SearchKind::Earliest, DEFAULT_CACHE_CAPACITY).with_limits(usize::MAX, 8);
+
+        assert!(lazy.is_match(haystack));
+        assert!(lazy.num_of_cached_states() <= 9);
+        assert!(lazy.cache_flushes() > 0);
+    }
+
+    #[test]
+    fn given_cache_too_small_for_the_input_when_trying_to_match_should_give_up() {
+        let query = format!("(a|b)*a{}c", "(a|b)".repeat(10));
+        let mut lazy = build(&query, SearchKind::Earliest, 1024);
+
+        // Every 10 bit number written out in a's and b's, so the DFA has to visit loads of distinct states
+        let haystack: String = (0..1024)
+            .map(|i: u32| format!("{:010b}", i).replace('0', "a").replace('1', "b"))
+            .collect();
+
+        assert_eq!(lazy.try_is_match(&haystack), Err(CacheExhausted));
+        assert!(!lazy.is_match(&haystack));
+    }
+
+    #[test]
+    fn given_patterns_when_finding_end_with_leftmost_longest_search_should_return_the_end_of_the_leftmost_longest_match(
+    ) {
+        let examples = [
+            ("ab|bcde", "abcde", Some(2)),
+            ("abcd|c", "abcd", Some(4)),
+            ("a+", "baaab", Some(4)),
+            ("(a|b)*c", "xxababcab", Some(7)),
+            ("b*", "abbb", Some(0)),
+            ("z", "abc", None),
+        ];
+
+        for (query, haystack, answer) in examples {
+            let mut lazy = build(query, SearchKind::LeftmostLongest, DEFAULT_CACHE_CAPACITY);
+
+            assert_eq!(lazy.find_end(haystack), answer);
+        }
+    }
+
+    #[test]
+    fn given_reversed_nfa_when_finding_start_should_return_the_furthest_left_start_of_a_match_at_the_end(
+    ) {
+        let examples = [
+            ("ab|bcde", "ab", Some(0)),
+            ("a+", "baaa", Some(1)),
+            ("(a|b)*c", "xxababc", Some(2)),
+            ("b*", "", Some(0)),
+            ("z", "abc", None),
+        ];
+
+        for (query, haystack, answer) in examples {
+            assert_eq!(build_reversed(query).rfind_start(haystack), answer);
+        }
+    }
+}
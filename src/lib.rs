@@ -1,138 +1,572 @@
+#![allow(clippy::needless_return)]
+
+pub mod aho_corasick;
 pub mod automata;
+pub mod builder;
+pub mod case_fold;
+pub mod char_classes;
+pub mod complete_dfa;
+pub mod dense_dfa;
 pub mod dfa;
+pub mod dot;
+pub mod explain;
+pub mod glushkov;
 pub mod graph;
+pub mod lazy_dfa;
+pub mod literals;
+pub mod matcher;
+pub mod memmem;
 pub mod nfa;
+pub mod pikevm;
+pub mod pool;
 pub mod postfixer;
 pub mod regex;
+pub mod regex_set;
+pub mod sample;
+pub mod state_elimination;
+pub mod stream;
+
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{self, Read},
+};
+
+use builder::SizeLimits;
+use complete_dfa::CompleteDfa;
+use dense_dfa::DenseDfa;
+use nfa::{build_multi_nfa, get_nfa_alphabet};
+use regex::{literal_postfix, RegexSymbol};
+use sample::{random_match, Rng, ShortestMatches};
+use stream::StreamSearcher;
+
+pub use builder::{BuildError, RegexBuilder};
+pub use matcher::Regex;
+pub use nfa::NfaConstruction;
+pub use regex_set::RegexSet;
+
+const DEFAULT_SAMPLE_COUNT: usize = 10;
+
+#[derive(Default)]
+pub struct Config {
+    // Set when the first argument names a subcommand, which gets run instead of searching
+    pub subcommand: Option<Subcommand>,
+    // Patterns given on the command line, a line matches if any of these or the ones in the pattern files do
+    pub patterns: Vec<String>,
+    pub pattern_files: Vec<String>,
+    // Only optional when just saving a DFA
+    pub file_path: Option<String>,
+    pub ignore_case: bool,
+    pub fixed_strings: bool,
+    pub save_dfa: Option<String>,
+    pub load_dfa: Option<String>,
+    // Print the automata in DOT instead of searching
+    pub dump_nfa: bool,
+    pub dump_dfa: bool,
+    // Report on each stage of compiling the patterns to stderr
+    pub debug_compile: bool,
+    // Show every step of matching this input instead of searching
+    pub trace: Option<String>,
+    // How many strings the sample subcommand prints
+    pub sample_count: usize,
+    // Set when sample should draw random strings of up to this length rather than the shortest ones
+    pub sample_max_len: Option<usize>,
+    pub seed: Option<u64>,
+}
 
-use std::{env, error::Error, fs};
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Subcommand {
+    // Check two patterns against each other
+    Compare(Comparison),
+    // Print strings the pattern matches
+    Sample,
+    // Print the simplest regex for what the pattern matches, which is the same for any pattern matching the same
+    Canonical,
+}
 
-use automata::AutomataState;
-use dfa::build_dfa;
-use graph::{Graph, NodeIndex};
-use nfa::build_nfa;
-use regex::get_alphabet;
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    // Whether two patterns match exactly the same strings
+    Equiv,
+    // Whether every string the first pattern matches is matched by the second too
+    Subset,
+}
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
+impl Subcommand {
+    fn from_name(name: &str) -> Option<Subcommand> {
+        return match name {
+            "--equiv" => Some(Subcommand::Compare(Comparison::Equiv)),
+            "--subset" => Some(Subcommand::Compare(Comparison::Subset)),
+            "--sample" => Some(Subcommand::Sample),
+            "--canonical" => Some(Subcommand::Canonical),
+            _ => None,
+        };
+    }
 }
 
 impl Config {
     pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
+        // Subcommands are flags, so a query like "sample" is still searched for, and only the very first argument
+        // can be one, so `mygrep -- --equiv poem.txt` searches for "--equiv"
+        if let Some(subcommand) = args.get(1).and_then(|arg| Subcommand::from_name(arg)) {
+            return Config::build_subcommand(subcommand, &args[2..]);
         }
 
-        let query = args[1].clone();
-        let file_path = args[2].clone();
+        let mut patterns: Vec<String> = Vec::new();
+        let mut pattern_files: Vec<String> = Vec::new();
+        let mut fixed_strings = false;
+        let mut save_dfa: Option<String> = None;
+        let mut load_dfa: Option<String> = None;
+        let mut dump_nfa = false;
+        let mut dump_dfa = false;
+        let mut debug_compile = false;
+        let mut trace: Option<String> = None;
+        let mut positional: Vec<String> = Vec::new();
+        let mut options_ended = false;
+
+        let mut args = args.iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            if options_ended {
+                positional.push(arg.clone());
+                continue;
+            }
+
+            match arg.as_str() {
+                "-F" | "--fixed-strings" => fixed_strings = true,
+                "-e" | "--regexp" => {
+                    patterns.push(args.next().ok_or("missing pattern for -e")?.clone())
+                }
+                "-f" | "--file" => {
+                    pattern_files.push(args.next().ok_or("missing pattern file for -f")?.clone())
+                }
+                "--save-dfa" => {
+                    save_dfa = Some(args.next().ok_or("missing path for --save-dfa")?.clone())
+                }
+                "--load-dfa" => {
+                    load_dfa = Some(args.next().ok_or("missing path for --load-dfa")?.clone())
+                }
+                "--dump-nfa" => dump_nfa = true,
+                "--dump-dfa" => dump_dfa = true,
+                "--debug-compile" => debug_compile = true,
+                "--trace" => trace = Some(args.next().ok_or("missing input for --trace")?.clone()),
+                // Lets a query that starts with a dash through
+                "--" => options_ended = true,
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        let has_patterns = !patterns.is_empty() || !pattern_files.is_empty();
+        let mut positional = positional.into_iter();
+
+        if load_dfa.is_some() {
+            // The loaded DFA already has the patterns built into it, and how they were read with them
+            if has_patterns || fixed_strings || save_dfa.is_some() {
+                return Err("--load-dfa can't be used along with patterns, -F or --save-dfa");
+            }
+        } else if !has_patterns {
+            // The query only comes first when it hasn't been given with -e or -f
+            patterns.push(positional.next().ok_or("not enough arguments")?);
+        }
+
+        let file_path = positional.next();
+
+        let only_compiling =
+            save_dfa.is_some() || dump_nfa || dump_dfa || debug_compile || trace.is_some();
+
+        if file_path.is_none() && !only_compiling {
+            return Err("not enough arguments");
+        }
 
         let ignore_case = env::var("IGNORE_CASE").is_ok();
 
         Ok(Config {
-            query,
+            subcommand: None,
+            patterns,
+            pattern_files,
             file_path,
             ignore_case,
+            fixed_strings,
+            save_dfa,
+            load_dfa,
+            dump_nfa,
+            dump_dfa,
+            debug_compile,
+            trace,
+            ..Config::default()
         })
     }
-}
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+    fn build_subcommand(subcommand: Subcommand, args: &[String]) -> Result<Config, &'static str> {
+        if subcommand == Subcommand::Sample {
+            return Config::build_sample(args);
+        }
+
+        let patterns: Vec<String> = args.iter().filter(|arg| *arg != "--").cloned().collect();
 
-    let results = search(&config.query, &contents, config.ignore_case);
+        if subcommand == Subcommand::Canonical && patterns.len() != 1 {
+            return Err("--canonical needs exactly one pattern");
+        }
 
-    for line in results {
-        println!("{line}");
+        if subcommand != Subcommand::Canonical && patterns.len() != 2 {
+            return Err("--equiv and --subset need exactly two patterns");
+        }
+
+        Ok(Config {
+            subcommand: Some(subcommand),
+            patterns,
+            ignore_case: env::var("IGNORE_CASE").is_ok(),
+            ..Config::default()
+        })
     }
 
-    Ok(())
+    fn build_sample(args: &[String]) -> Result<Config, &'static str> {
+        let mut sample_count = DEFAULT_SAMPLE_COUNT;
+        let mut sample_max_len: Option<usize> = None;
+        let mut seed: Option<u64> = None;
+        let mut patterns: Vec<String> = Vec::new();
+        let mut options_ended = false;
+
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if options_ended {
+                patterns.push(arg.clone());
+                continue;
+            }
+
+            match arg.as_str() {
+                "-n" | "--count" => {
+                    sample_count = args
+                        .next()
+                        .ok_or("missing count for -n")?
+                        .parse()
+                        .map_err(|_| "the count for -n has to be a number")?
+                }
+                "--random" => {
+                    sample_max_len = Some(
+                        args.next()
+                            .ok_or("missing maximum length for --random")?
+                            .parse()
+                            .map_err(|_| "the maximum length for --random has to be a number")?,
+                    )
+                }
+                "--seed" => {
+                    seed = Some(
+                        args.next()
+                            .ok_or("missing seed for --seed")?
+                            .parse()
+                            .map_err(|_| "the seed for --seed has to be a number")?,
+                    )
+                }
+                "--" => options_ended = true,
+                _ => patterns.push(arg.clone()),
+            }
+        }
+
+        if patterns.len() != 1 {
+            return Err("--sample needs exactly one pattern");
+        }
+
+        if seed.is_some() && sample_max_len.is_none() {
+            return Err("--seed can only be used along with --random");
+        }
+
+        Ok(Config {
+            subcommand: Some(Subcommand::Sample),
+            patterns,
+            ignore_case: env::var("IGNORE_CASE").is_ok(),
+            sample_count,
+            sample_max_len,
+            seed,
+            ..Config::default()
+        })
+    }
 }
 
-pub fn search<'a>(query: &str, contents: &'a str, ignore_case: bool) -> Vec<&'a str> {
-    let mut results = Vec::new();
+// Returns whether everything checked out, which is only ever false when equiv or subset finds a counterexample,
+// so scripts can tell from the exit code
+pub fn run(config: Config) -> Result<bool, Box<dyn Error>> {
+    if config.subcommand == Some(Subcommand::Sample) {
+        let rng = config.seed.map_or_else(Rng::from_time, Rng::new);
+
+        for string in sample(
+            &config.patterns[0],
+            config.ignore_case,
+            config.sample_count,
+            config.sample_max_len,
+            rng,
+            SizeLimits::default(),
+        )? {
+            println!("{}", string);
+        }
 
-    let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        return Ok(true);
+    }
 
-    let alphabet = get_alphabet(&postfix_regex);
+    if config.subcommand == Some(Subcommand::Canonical) {
+        println!(
+            "{}",
+            canonicalize(
+                &config.patterns[0],
+                config.ignore_case,
+                SizeLimits::default()
+            )?
+        );
 
-    let (handle, nfa) = build_nfa(postfix_regex);
+        return Ok(true);
+    }
 
-    let (start, dfa) = build_dfa(handle, nfa, alphabet);
+    if let Some(Subcommand::Compare(comparison)) = config.subcommand {
+        let verdict = compare(
+            comparison,
+            &config.patterns[0],
+            &config.patterns[1],
+            config.ignore_case,
+            SizeLimits::default(),
+        )?;
 
-    for line in contents.lines() {
-        if check_line_matches(start, &dfa, line, ignore_case) {
-            results.push(line);
+        println!("{}", verdict.explanation);
+
+        return Ok(verdict.holds);
+    }
+
+    if let Some(dfa_path) = config.load_dfa {
+        let dfa = DenseDfa::from_bytes(&fs::read(dfa_path)?)?;
+
+        StreamSearcher::with_dense_dfa(&dfa)
+            .search(open_input(&config.file_path.unwrap())?, io::stdout().lock())?;
+
+        return Ok(true);
+    }
+
+    let mut patterns = config.patterns;
+
+    for pattern_file in config.pattern_files {
+        patterns.extend(parse_patterns(&fs::read_to_string(pattern_file)?));
+    }
+
+    if config.debug_compile {
+        for pattern in patterns.iter() {
+            if config.fixed_strings {
+                eprintln!("query    {}\nfixed string, so nothing to compile", pattern);
+            } else {
+                eprint!("{}", explain::explain_compile(pattern, config.ignore_case)?);
+            }
         }
     }
 
-    return results;
-}
+    if let Some(input) = config.trace {
+        let postfix_regexes = compile_patterns(&patterns, config.fixed_strings)?;
+        let limits = SizeLimits::default();
 
-fn check_line_matches(
-    start_of_dfa: NodeIndex,
-    dfa: &Graph<AutomataState, char>,
-    line: &str,
-    ignore_case: bool,
-) -> bool {
-    for i in 0..line.len() {
-        let sub_line = &line[i..];
+        limits.check_nfa(&postfix_regexes)?;
+
+        print!(
+            "{}",
+            explain::trace(postfix_regexes, &input, config.ignore_case, limits)?
+        );
+
+        return Ok(true);
+    }
+
+    if config.dump_nfa || config.dump_dfa {
+        let postfix_regexes = compile_patterns(&patterns, config.fixed_strings)?;
+        let limits = SizeLimits::default();
+
+        limits.check_nfa(&postfix_regexes)?;
 
-        let automata_has_accepted = run_automata(start_of_dfa, dfa, sub_line, ignore_case);
+        let (handle, nfa) = build_multi_nfa(postfix_regexes, config.ignore_case, limits)?;
 
-        if automata_has_accepted {
-            return true;
+        if config.dump_nfa {
+            print!("{}", dot::to_dot("nfa", &nfa, handle.get_start_state()));
         }
+
+        if config.dump_dfa {
+            let alphabet = get_nfa_alphabet(&nfa);
+            let (start, dfa) = dfa::build_dfa_with_limits(handle, &nfa, alphabet, limits)?;
+
+            print!("{}", dot::to_dot("dfa", &dfa, start));
+        }
+
+        return Ok(true);
+    }
+
+    if let Some(dfa_path) = config.save_dfa {
+        let postfix_regexes = compile_patterns(&patterns, config.fixed_strings)?;
+        let dfa =
+            DenseDfa::from_postfix(postfix_regexes, config.ignore_case, SizeLimits::default())?;
+
+        fs::write(dfa_path, dfa.to_bytes()?)?;
     }
 
-    return false;
+    let file_path = match config.file_path {
+        Some(file_path) => file_path,
+        None => return Ok(true),
+    };
+
+    // Everything gets compiled into the one matcher, rather than running over the file once per pattern
+    let regex = if config.fixed_strings {
+        Regex::fixed_strings(&patterns, config.ignore_case)?
+    } else {
+        Regex::new_many(&patterns, config.ignore_case)?
+    };
+
+    // The input is read a chunk at a time, so files too big to fit in memory and pipes work too
+    StreamSearcher::new(&regex).search(open_input(&file_path)?, io::stdout().lock())?;
+
+    Ok(true)
 }
 
-fn run_automata(
-    start_of_dfa: NodeIndex,
-    dfa: &Graph<AutomataState, char>,
-    sub_line: &str,
-    ignore_case: bool,
-) -> bool {
-    let mut current_node = start_of_dfa;
+// A file path of - means standard input, same as grep
+fn open_input(file_path: &str) -> io::Result<Box<dyn Read>> {
+    if file_path == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
 
-    for c in sub_line.chars() {
-        let mut can_progress = false;
-        let outgoing_edges = dfa.outgoing_edges(current_node).unwrap();
+    return Ok(Box::new(File::open(file_path)?));
+}
 
-        for edge in outgoing_edges {
-            let data = dfa.get_edge_data(&edge).unwrap();
-            let label = *data.borrow();
+// What compare found, and how it came to it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Verdict {
+    // Whether the patterns are equivalent, or the first a subset of the second
+    pub holds: bool,
+    pub explanation: String,
+}
 
-            if ignore_case {
-                if label.to_lowercase().to_string() == c.to_lowercase().to_string() {
-                    current_node = dfa.traverse(edge).unwrap();
-                    can_progress = true;
-                }
-            } else {
-                if label == c {
-                    current_node = dfa.traverse(edge).unwrap();
-                    can_progress = true;
-                }
-            }
+// Checks the languages of the two patterns against each other, and explains the shortest string that tells
+// them apart if the check fails. Every DFA state gets built, so a DFA going over the limits is an error.
+pub fn compare(
+    comparison: Comparison,
+    left: &str,
+    right: &str,
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<Verdict, BuildError> {
+    let left_dfa = CompleteDfa::with_limits(left, ignore_case, limits)?;
+    let right_dfa = CompleteDfa::with_limits(right, ignore_case, limits)?;
+
+    let counterexample = match comparison {
+        Comparison::Equiv => left_dfa.find_difference_with_limits(&right_dfa, limits)?,
+        Comparison::Subset => left_dfa.find_not_in_with_limits(&right_dfa, limits)?,
+    };
+
+    let holds = |explanation: String| Verdict {
+        holds: true,
+        explanation,
+    };
+
+    let counterexample = match (comparison, counterexample) {
+        (Comparison::Equiv, None) => return Ok(holds("equivalent".to_string())),
+        (Comparison::Subset, None) => {
+            return Ok(holds(format!("{} is a subset of {}", left, right)))
         }
+        (_, Some(counterexample)) => counterexample,
+    };
+
+    let (matched_by, not_by) = if left_dfa.is_match(&counterexample) {
+        (left, right)
+    } else {
+        (right, left)
+    };
+
+    let verdict = match comparison {
+        Comparison::Equiv => "not equivalent",
+        Comparison::Subset => "not a subset",
+    };
+
+    return Ok(Verdict {
+        holds: false,
+        explanation: format!(
+            "{}: {:?} is matched by {} but not by {}",
+            verdict, counterexample, matched_by, not_by
+        ),
+    });
+}
+
+// The regex for the minimal DFA of the pattern, so patterns matching the same strings give the same regex
+pub fn canonicalize(
+    query: &str,
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<String, BuildError> {
+    return Ok(CompleteDfa::with_limits(query, ignore_case, limits)?
+        .minimize()
+        .to_regex());
+}
+
+// Up to count strings the pattern matches as a whole. Either the shortest ones in length-lexicographic order, or
+// with max_len random ones of at most that length, which can repeat.
+pub fn sample(
+    query: &str,
+    ignore_case: bool,
+    count: usize,
+    max_len: Option<usize>,
+    mut rng: Rng,
+    limits: SizeLimits,
+) -> Result<Vec<String>, BuildError> {
+    let dfa = CompleteDfa::with_limits(query, ignore_case, limits)?;
+
+    let Some(max_len) = max_len else {
+        return Ok(ShortestMatches::new(&dfa).take(count).collect());
+    };
+
+    return Ok((0..count)
+        .map_while(|_| random_match(&dfa, max_len, &mut rng))
+        .collect());
+}
 
-        if !can_progress {
-            break;
+fn compile_patterns(
+    patterns: &[String],
+    fixed_strings: bool,
+) -> Result<Vec<VecDeque<RegexSymbol>>, String> {
+    if fixed_strings {
+        return Ok(patterns.iter().map(|p| literal_postfix(p)).collect());
+    }
+
+    return patterns
+        .iter()
+        .map(|p| postfixer::transform(p.clone()))
+        .collect();
+}
+
+// Reads a pattern file, one pattern per line, skipping blank lines and lines starting with a # comment
+pub fn parse_patterns(contents: &str) -> Vec<String> {
+    return contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| line.to_string())
+        .collect();
+}
+
+// Returns the lines containing a match, use Regex::find_iter to get where the matches themselves are
+pub fn search_lines<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
+    let mut results = Vec::new();
+
+    for line in contents.lines() {
+        if check_line_matches(regex, line) {
+            results.push(line);
         }
     }
 
-    let data = dfa.get_node_data(&current_node).unwrap();
-    let label = data.borrow();
+    return results;
+}
 
-    return label.is_accepting();
+fn check_line_matches(regex: &Regex, line: &str) -> bool {
+    return regex.is_match(line);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn search<'a>(query: &str, contents: &'a str, ignore_case: bool) -> Vec<&'a str> {
+        return search_lines(&Regex::new(query, ignore_case).unwrap(), contents);
+    }
+
     #[test]
     fn given_basic_input_when_searching_in_case_sensitive_mode_should_return_answers_and_respect_case(
     ) {
@@ -149,6 +583,315 @@ Duct tape.";
         );
     }
 
+    #[test]
+    fn given_fixed_strings_flag_when_building_config_should_treat_the_rest_as_query_and_file_path()
+    {
+        let args: Vec<String> = ["mygrep", "-F", "a(b|c)*", "poem.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let config = Config::build(&args).unwrap();
+
+        assert!(config.fixed_strings);
+        assert_eq!(config.patterns, vec!["a(b|c)*"]);
+        assert_eq!(config.file_path.as_deref(), Some("poem.txt"));
+
+        let args: Vec<String> = ["mygrep", "--", "-F", "poem.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let config = Config::build(&args).unwrap();
+
+        assert!(!config.fixed_strings);
+        assert_eq!(config.patterns, vec!["-F"]);
+    }
+
+    #[test]
+    fn given_repeated_pattern_flags_when_building_config_should_collect_them_all() {
+        let args: Vec<String> = [
+            "mygrep",
+            "-e",
+            "you",
+            "-f",
+            "rules.txt",
+            "-e",
+            "us",
+            "poem.txt",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let config = Config::build(&args).unwrap();
+
+        assert_eq!(config.patterns, vec!["you", "us"]);
+        assert_eq!(config.pattern_files, vec!["rules.txt"]);
+        assert_eq!(config.file_path.as_deref(), Some("poem.txt"));
+
+        let args: Vec<String> = ["mygrep", "-e", "you"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert!(Config::build(&args).is_err());
+    }
+
+    #[test]
+    fn given_dfa_flags_when_building_config_should_only_need_what_each_mode_uses() {
+        let build = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            Config::build(&args)
+        };
+
+        let config = build(&["mygrep", "--save-dfa", "rules.dfa", "-f", "rules.txt"]).unwrap();
+
+        assert_eq!(config.save_dfa.as_deref(), Some("rules.dfa"));
+        assert_eq!(config.file_path, None);
+
+        let config = build(&["mygrep", "--load-dfa", "rules.dfa", "poem.txt"]).unwrap();
+
+        assert_eq!(config.load_dfa.as_deref(), Some("rules.dfa"));
+        assert!(config.patterns.is_empty());
+        assert_eq!(config.file_path.as_deref(), Some("poem.txt"));
+
+        assert!(build(&["mygrep", "--load-dfa", "rules.dfa", "-e", "you", "poem.txt"]).is_err());
+        assert!(build(&[
+            "mygrep",
+            "--load-dfa",
+            "rules.dfa",
+            "-f",
+            "rules.txt",
+            "poem.txt"
+        ])
+        .is_err());
+        assert!(build(&["mygrep", "-F", "--load-dfa", "rules.dfa", "poem.txt"]).is_err());
+        assert!(build(&["mygrep", "--load-dfa", "rules.dfa"]).is_err());
+
+        let config = build(&["mygrep", "--dump-nfa", "--dump-dfa", "(you)|(us)"]).unwrap();
+
+        assert!(config.dump_nfa && config.dump_dfa);
+        assert!(!config.debug_compile);
+        assert_eq!(config.patterns, vec!["(you)|(us)"]);
+        assert_eq!(config.file_path, None);
+
+        let config = build(&["mygrep", "--debug-compile", "(you)|(us)"]).unwrap();
+
+        assert!(config.debug_compile);
+        assert_eq!(config.file_path, None);
+
+        let config = build(&["mygrep", "--trace", "Who are you?", "(you)|(us)"]).unwrap();
+
+        assert_eq!(config.trace.as_deref(), Some("Who are you?"));
+        assert_eq!(config.patterns, vec!["(you)|(us)"]);
+    }
+
+    #[test]
+    fn given_subcommand_as_the_first_argument_when_building_config_should_take_two_patterns() {
+        let build = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            Config::build(&args)
+        };
+
+        let config = build(&["mygrep", "--equiv", "a+", "aa*"]).unwrap();
+
+        assert_eq!(
+            config.subcommand,
+            Some(Subcommand::Compare(Comparison::Equiv))
+        );
+        assert_eq!(config.patterns, vec!["a+", "aa*"]);
+
+        let config = build(&["mygrep", "--subset", "--", "-a", "(-|a)*"]).unwrap();
+
+        assert_eq!(
+            config.subcommand,
+            Some(Subcommand::Compare(Comparison::Subset))
+        );
+        assert_eq!(config.patterns, vec!["-a", "(-|a)*"]);
+
+        assert!(build(&["mygrep", "--equiv", "a+"]).is_err());
+
+        // Anywhere else it's just a query
+        let config = build(&["mygrep", "--", "--equiv", "poem.txt"]).unwrap();
+
+        assert_eq!(config.subcommand, None);
+        assert_eq!(config.patterns, vec!["--equiv"]);
+
+        // Without the dashes it's a query like any other
+        let config = build(&["mygrep", "sample", "poem.txt"]).unwrap();
+
+        assert_eq!(config.subcommand, None);
+        assert_eq!(config.patterns, vec!["sample"]);
+        assert_eq!(config.file_path.as_deref(), Some("poem.txt"));
+    }
+
+    #[test]
+    fn given_sample_subcommand_when_building_config_should_take_one_pattern_and_its_options() {
+        let build = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            Config::build(&args)
+        };
+
+        let config = build(&["mygrep", "--sample", "a+"]).unwrap();
+
+        assert_eq!(config.subcommand, Some(Subcommand::Sample));
+        assert_eq!(config.patterns, vec!["a+"]);
+        assert_eq!(config.sample_count, DEFAULT_SAMPLE_COUNT);
+        assert_eq!(config.sample_max_len, None);
+
+        let config = build(&[
+            "mygrep", "--sample", "-n", "3", "--random", "20", "--seed", "9", "--", "-b",
+        ])
+        .unwrap();
+
+        assert_eq!(config.patterns, vec!["-b"]);
+        assert_eq!(config.sample_count, 3);
+        assert_eq!(config.sample_max_len, Some(20));
+        assert_eq!(config.seed, Some(9));
+
+        assert!(build(&["mygrep", "--sample", "a", "b"]).is_err());
+        assert!(build(&["mygrep", "--sample", "-n", "lots", "a"]).is_err());
+        assert!(build(&["mygrep", "--sample", "--seed", "9", "a"]).is_err());
+    }
+
+    #[test]
+    fn given_pattern_when_sampling_should_give_the_shortest_or_random_matches() {
+        assert_eq!(
+            sample(
+                "ERROR (disk|net)",
+                false,
+                10,
+                None,
+                Rng::new(1),
+                SizeLimits::default()
+            )
+            .unwrap(),
+            vec!["ERROR net", "ERROR disk"]
+        );
+
+        let random = sample(
+            "id=(0|1)+",
+            false,
+            20,
+            Some(6),
+            Rng::new(1),
+            SizeLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(random.len(), 20);
+        assert!(random
+            .iter()
+            .all(|s| s.starts_with("id=") && s.len() <= 6 && s.len() > 3));
+
+        assert!(sample("(a", false, 10, None, Rng::new(1), SizeLimits::default()).is_err());
+    }
+
+    #[test]
+    fn given_patterns_for_the_same_language_when_canonicalizing_should_give_the_same_regex() {
+        let canonical = |query: &str| canonicalize(query, false, SizeLimits::default()).unwrap();
+
+        assert_eq!(canonical("(a*b*)*"), "(a|b)*");
+        assert_eq!(canonical("(a|b)*"), "(a|b)*");
+        assert_eq!(canonical("colo(u)?r"), "colou?r");
+        assert_eq!(canonical("ab|ac"), canonical("a(c|b)"));
+        assert_eq!(canonical("ab|b"), "a?b");
+
+        let config = Config::build(&[
+            "mygrep".to_string(),
+            "--canonical".to_string(),
+            "a+".to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(config.subcommand, Some(Subcommand::Canonical));
+        assert_eq!(config.patterns, vec!["a+"]);
+    }
+
+    #[test]
+    fn given_two_patterns_when_comparing_should_explain_the_counterexample() {
+        let check = |subcommand, left, right, ignore_case| {
+            let verdict = compare(subcommand, left, right, ignore_case, SizeLimits::default());
+            let verdict = verdict.unwrap();
+
+            return (verdict.holds, verdict.explanation);
+        };
+
+        assert_eq!(
+            check(Comparison::Equiv, "(a|b)*", "(a*b*)*", false),
+            (true, "equivalent".to_string())
+        );
+        assert_eq!(
+            check(Comparison::Equiv, "a*b*", "(a|b)*", false),
+            (
+                false,
+                "not equivalent: \"ba\" is matched by (a|b)* but not by a*b*".to_string()
+            )
+        );
+        assert_eq!(
+            check(Comparison::Subset, "ab", "(a|b)+", false),
+            (true, "ab is a subset of (a|b)+".to_string())
+        );
+        assert_eq!(
+            check(Comparison::Subset, "(a|b)+", "ab", false),
+            (
+                false,
+                "not a subset: \"a\" is matched by (a|b)+ but not by ab".to_string()
+            )
+        );
+        assert_eq!(
+            check(Comparison::Equiv, "rust", "RUST", true),
+            (true, "equivalent".to_string())
+        );
+    }
+
+    #[test]
+    fn given_exponential_pattern_when_checking_its_language_should_hit_the_dfa_limit_not_hang() {
+        // The DFA needs a state for every combination of the last 13 characters
+        let query = format!("(a|b)*a{}", "(a|b)".repeat(12));
+        let limits = SizeLimits {
+            dfa_states: 1000,
+            ..SizeLimits::default()
+        };
+        let too_many = Some(BuildError::TooManyDfaStates { limit: 1000 });
+
+        let result = compare(Comparison::Equiv, &query, "a", false, limits);
+        assert_eq!(result.err(), too_many);
+
+        let result = compare(Comparison::Subset, "a", &query, false, limits);
+        assert_eq!(result.err(), too_many);
+
+        assert_eq!(canonicalize(&query, false, limits).err(), too_many);
+
+        let result = sample(&query, false, 10, None, Rng::new(1), limits);
+        assert_eq!(result.err(), too_many);
+    }
+
+    #[test]
+    fn given_pattern_file_when_parsing_should_skip_blank_lines_and_comments() {
+        let contents = "\
+# Rules for the poem
+(you)|(us)
+
+  # indented comment
+frog
+   ";
+
+        assert_eq!(parse_patterns(contents), vec!["(you)|(us)", "frog"]);
+    }
+
+    #[test]
+    fn given_fixed_string_when_searching_should_match_operators_literally() {
+        let regex = Regex::fixed_string("a(b|c)*", false).unwrap();
+        let contents = "\
+abcbc
+a(b|c)* is a regex
+ac";
+
+        assert_eq!(vec!["a(b|c)* is a regex"], search_lines(&regex, contents));
+    }
+
     #[test]
     fn given_basic_input_when_searching_in_case_insensitive_mode_should_return_answers_and_not_respect_case(
     ) {
@@ -162,6 +905,18 @@ Trust me.";
         assert_eq!(vec!["Rust:", "Trust me."], search(query, contents, true));
     }
 
+    #[test]
+    fn given_non_ascii_input_when_searching_in_case_insensitive_mode_should_use_unicode_case_folding(
+    ) {
+        let query = "σοφια";
+        let contents = "\
+ΣΟΦΙΑ
+σοφιας
+Sofia";
+
+        assert_eq!(vec!["ΣΟΦΙΑ", "σοφιας"], search(query, contents, true));
+    }
+
     #[test]
     fn given_test_input_when_searching_with_alternation_operator_should_correctly_return_answers() {
         let query = "(safe)|(three)";
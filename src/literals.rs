@@ -0,0 +1,347 @@
+/* Works out which literal strings any match of a pattern has to contain, by walking the postfix regex with a
+ * stack in the same way the NFA gets built. For each sub expression we track:
+ *
+ * - exact: the only string it can match, if it's just a plain literal
+ * - prefix/suffix: a string every match of it has to start/end with
+ * - inner: the longest string we know every match of it has to contain somewhere
+ *
+ * e.g. for "ERROR (disk|net)" every match starts with "ERROR ", and for "(a|b)*failed(x|y)" every match
+ * contains "failed". Those literals can then be searched for far faster than running the automata, so the
+ * Prefilter uses them to throw away input that can't match and to skip ahead to where a match could start.
+ */
+
+use std::collections::VecDeque;
+
+use crate::{memmem::Finder, regex::RegexSymbol};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RequiredLiterals {
+    exact: Option<String>,
+    prefix: String,
+    suffix: String,
+    inner: String,
+}
+
+pub struct Prefilter {
+    prefix: Option<Finder>,
+    inner: Option<Finder>,
+}
+
+pub fn extract(postfix_regex: &VecDeque<RegexSymbol>) -> RequiredLiterals {
+    let mut stack: Vec<RequiredLiterals> = Vec::new();
+
+    for symbol in postfix_regex {
+        let literals = match symbol {
+            RegexSymbol::Char(c) => RequiredLiterals::literal(c.to_string()),
+            RegexSymbol::Concat => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+
+                RequiredLiterals::concat(left, right)
+            }
+            RegexSymbol::Alternation => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+
+                RequiredLiterals::alternate(left, right)
+            }
+            // Anything matching both sides has to have what the left side needs, though it might not match
+            // the left side's literal at all
+            RegexSymbol::Intersection => {
+                stack.pop().unwrap();
+
+                RequiredLiterals {
+                    exact: None,
+                    ..stack.pop().unwrap()
+                }
+            }
+            RegexSymbol::Plus => RequiredLiterals::repeat(stack.pop().unwrap()),
+            RegexSymbol::Optional | RegexSymbol::Star | RegexSymbol::Complement => {
+                stack.pop().unwrap();
+
+                RequiredLiterals::none()
+            }
+            _ => panic!("Error - Parenthesis should have been removed in postfixing stage!"),
+        };
+
+        stack.push(literals);
+    }
+
+    return stack.pop().unwrap_or_else(RequiredLiterals::none);
+}
+
+// Returns the literals the pattern is an alternation of, e.g. "(you)|(us)|them" is just ["you", "us", "them"],
+// or None if it uses any other operators in a way that can match more than a fixed set of strings
+pub fn alternatives(postfix_regex: &VecDeque<RegexSymbol>) -> Option<Vec<String>> {
+    let mut stack: Vec<Vec<String>> = Vec::new();
+
+    for symbol in postfix_regex {
+        let alternatives = match symbol {
+            RegexSymbol::Char(c) => vec![c.to_string()],
+            RegexSymbol::Concat => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+
+                // Concatenating alternations multiplies out, which isn't worth it
+                if left.len() != 1 || right.len() != 1 {
+                    return None;
+                }
+
+                vec![format!("{}{}", left[0], right[0])]
+            }
+            RegexSymbol::Alternation => {
+                let right = stack.pop().unwrap();
+                let mut left = stack.pop().unwrap();
+
+                left.extend(right);
+                left
+            }
+            _ => return None,
+        };
+
+        stack.push(alternatives);
+    }
+
+    return stack.pop();
+}
+
+impl RequiredLiterals {
+    fn none() -> RequiredLiterals {
+        return RequiredLiterals {
+            exact: None,
+            prefix: String::new(),
+            suffix: String::new(),
+            inner: String::new(),
+        };
+    }
+
+    fn literal(s: String) -> RequiredLiterals {
+        return RequiredLiterals {
+            exact: Some(s.clone()),
+            prefix: s.clone(),
+            suffix: s.clone(),
+            inner: s,
+        };
+    }
+
+    fn concat(left: RequiredLiterals, right: RequiredLiterals) -> RequiredLiterals {
+        if let (Some(l), Some(r)) = (&left.exact, &right.exact) {
+            return RequiredLiterals::literal(format!("{}{}", l, r));
+        }
+
+        let prefix = match &left.exact {
+            Some(l) => format!("{}{}", l, right.prefix),
+            None => left.prefix,
+        };
+
+        let suffix = match &right.exact {
+            Some(r) => format!("{}{}", left.suffix, r),
+            None => right.suffix,
+        };
+
+        // Whatever the left side ends with runs straight into whatever the right side starts with
+        let across = format!("{}{}", left.suffix, right.prefix);
+
+        let inner = [
+            left.inner,
+            right.inner,
+            across,
+            prefix.clone(),
+            suffix.clone(),
+        ]
+        .into_iter()
+        .max_by_key(|s| s.len())
+        .unwrap();
+
+        return RequiredLiterals {
+            exact: None,
+            prefix,
+            suffix,
+            inner,
+        };
+    }
+
+    fn alternate(left: RequiredLiterals, right: RequiredLiterals) -> RequiredLiterals {
+        if left.exact.is_some() && left.exact == right.exact {
+            return left;
+        }
+
+        let prefix = common_prefix(&left.prefix, &right.prefix);
+        let suffix = common_suffix(&left.suffix, &right.suffix);
+
+        // Either side could have matched, so all we know for sure is what they have in common
+        let inner = if prefix.len() >= suffix.len() {
+            prefix.clone()
+        } else {
+            suffix.clone()
+        };
+
+        return RequiredLiterals {
+            exact: None,
+            prefix,
+            suffix,
+            inner,
+        };
+    }
+
+    // One or more repetitions still has to match the whole thing at least once
+    fn repeat(literals: RequiredLiterals) -> RequiredLiterals {
+        return RequiredLiterals {
+            exact: None,
+            ..literals
+        };
+    }
+
+    pub fn get_exact(&self) -> Option<&str> {
+        return self.exact.as_deref();
+    }
+
+    pub fn get_prefix(&self) -> &str {
+        return &self.prefix;
+    }
+
+    pub fn get_suffix(&self) -> &str {
+        return &self.suffix;
+    }
+
+    pub fn get_inner(&self) -> &str {
+        return &self.inner;
+    }
+}
+
+impl Prefilter {
+    // Returns None if there's nothing worth searching for ahead of running the automata
+    pub fn new(literals: &RequiredLiterals) -> Option<Prefilter> {
+        let prefix = literals.get_prefix();
+        let inner = literals.get_inner();
+
+        if prefix.is_empty() && inner.is_empty() {
+            return None;
+        }
+
+        let prefix_finder = if prefix.is_empty() {
+            None
+        } else {
+            Some(Finder::new(prefix))
+        };
+
+        // The prefix gets checked anyway, so the inner literal's only worth it if it tells us something more
+        let inner_finder = if inner.len() > prefix.len() {
+            Some(Finder::new(inner))
+        } else {
+            None
+        };
+
+        return Some(Prefilter {
+            prefix: prefix_finder,
+            inner: inner_finder,
+        });
+    }
+
+    // Returns the first position a match could start at in the haystack, or None if it can't contain a match
+    pub fn find_candidate(&self, haystack: &str) -> Option<usize> {
+        let mut start = 0;
+
+        if let Some(prefix) = &self.prefix {
+            start = prefix.find(haystack.as_bytes())?;
+        }
+
+        if let Some(inner) = &self.inner {
+            inner.find(&haystack.as_bytes()[start..])?;
+        }
+
+        return Some(start);
+    }
+}
+
+fn common_prefix(left: &str, right: &str) -> String {
+    return left
+        .chars()
+        .zip(right.chars())
+        .take_while(|(l, r)| l == r)
+        .map(|(l, _)| l)
+        .collect();
+}
+
+fn common_suffix(left: &str, right: &str) -> String {
+    let reversed: String = left
+        .chars()
+        .rev()
+        .zip(right.chars().rev())
+        .take_while(|(l, r)| l == r)
+        .map(|(l, _)| l)
+        .collect();
+
+    return reversed.chars().rev().collect();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::postfixer;
+
+    fn extract_from(query: &str) -> RequiredLiterals {
+        return extract(&postfixer::transform(query.to_string()).unwrap());
+    }
+
+    #[test]
+    fn given_patterns_when_extracting_literals_should_find_the_required_prefix_suffix_and_inner_literals(
+    ) {
+        let examples = [
+            ("duct", Some("duct"), "duct", "duct", "duct"),
+            ("ERROR (disk|net)", None, "ERROR ", "", "ERROR "),
+            ("(a|b)*failed(x|y)", None, "", "", "failed"),
+            ("(abc|abd)e", None, "ab", "e", "ab"),
+            ("(xa|ya)(bz|bw)", None, "", "", "ab"),
+            ("(ab)+c", None, "ab", "abc", "abc"),
+            ("a*", None, "", "", ""),
+            ("a?b", None, "", "b", "b"),
+        ];
+
+        for (query, exact, prefix, suffix, inner) in examples {
+            let literals = extract_from(query);
+
+            assert_eq!(literals.get_exact(), exact, "{}", query);
+            assert_eq!(literals.get_prefix(), prefix, "{}", query);
+            assert_eq!(literals.get_suffix(), suffix, "{}", query);
+            assert_eq!(literals.get_inner(), inner, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_prefilter_when_finding_candidates_should_skip_to_the_prefix_and_reject_lines_missing_literals(
+    ) {
+        let prefilter = Prefilter::new(&extract_from("ERROR (disk|net)")).unwrap();
+
+        assert_eq!(prefilter.find_candidate("12:00 ERROR net down"), Some(6));
+        assert_eq!(prefilter.find_candidate("12:00 WARN net slow"), None);
+
+        let prefilter = Prefilter::new(&extract_from("(a|b)*failed(x|y)")).unwrap();
+
+        assert_eq!(prefilter.find_candidate("abfailedx"), Some(0));
+        assert_eq!(prefilter.find_candidate("abfaile"), None);
+    }
+
+    #[test]
+    fn given_patterns_when_finding_alternatives_should_only_find_them_for_alternations_of_literals()
+    {
+        let examples = [
+            ("(you)|(us)|them", Some(vec!["you", "us", "them"])),
+            ("duct", Some(vec!["duct"])),
+            ("(you|us)!", None),
+            ("you|us+", None),
+        ];
+
+        for (query, answer) in examples {
+            let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+            let answer = answer.map(|a| a.iter().map(|s| s.to_string()).collect());
+
+            assert_eq!(alternatives(&postfix_regex), answer, "{}", query);
+        }
+    }
+
+    #[test]
+    fn given_pattern_without_required_literals_when_building_prefilter_should_not_build_one() {
+        assert!(Prefilter::new(&extract_from("a*|b")).is_none());
+    }
+}
@@ -10,8 +10,13 @@ fn main() {
         process::exit(1);
     });
 
-    if let Err(e) = mygrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    match mygrep::run(config) {
+        Ok(true) => (),
+        // equiv or subset found a counterexample, which has already been printed
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(1);
+        }
     }
 }
@@ -0,0 +1,926 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    aho_corasick::AhoCorasick,
+    automata::{AutomataComponent, AutomataLabel, AutomataState},
+    builder::{BuildError, RegexBuilder, SizeLimits},
+    case_fold::simple_case_fold_set,
+    complete_dfa::CompleteDfa,
+    graph::Graph,
+    lazy_dfa::{
+        CacheExhausted, LazyDfa, SearchKind, StateId, DEFAULT_CACHE_CAPACITY, MAX_CACHE_FLUSHES,
+    },
+    literals::{self, Prefilter},
+    memmem::Horspool,
+    nfa::{build_multi_nfa_with, reverse_nfa, NfaConstruction},
+    pikevm::PikeVm,
+    pool::{Pool, PoolGuard},
+    regex::{literal_postfix, RegexSymbol},
+};
+
+// A compiled query, along with whichever engine suits it best. Nothing about it changes once it's compiled (the
+// lazy DFA caches are kept in a pool, see pool.rs), so it can be shared between threads, and cloning it only
+// clones the Arc.
+#[derive(Clone)]
+pub struct Regex {
+    engine: Arc<Engine>,
+}
+
+enum Engine {
+    Automata(Box<Automata>),
+    // Plain strings don't need any automata at all
+    Literal(Horspool),
+    // Nor do alternations of them, however many there are
+    Literals(AhoCorasick),
+}
+
+// Searches run on the lazy DFAs until it turns out the DFA won't fit in the cache, after which we switch
+// to the Pike VM for good, since that's guaranteed linear in the size of the input.
+// Before either of them gets to see the input, the prefilter skips ahead to where a match could start.
+struct Automata {
+    prefilter: Option<Prefilter>,
+    dfas: Pool<LazyDfas>,
+    pike_vm: PikeVm,
+    dfa_exhausted: AtomicBool,
+}
+
+// One search's worth of lazy DFA caches
+#[derive(Clone)]
+struct LazyDfas {
+    dfa: LazyDfa,
+    forward_dfa: LazyDfa,
+    reverse_dfa: LazyDfa,
+}
+
+// The leftmost-longest match in a haystack, as a byte range into it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+    literal: Option<usize>,
+}
+
+impl Regex {
+    pub fn new(query: &str, ignore_case: bool) -> Result<Regex, BuildError> {
+        return RegexBuilder::new().ignore_case(ignore_case).build(query);
+    }
+
+    pub fn with_cache_capacity(
+        query: &str,
+        ignore_case: bool,
+        cache_capacity: usize,
+    ) -> Result<Regex, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .cache_capacity(cache_capacity)
+            .build(query);
+    }
+
+    // See RegexBuilder::build_many
+    pub fn new_many<S: AsRef<str>>(queries: &[S], ignore_case: bool) -> Result<Regex, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .build_many(queries);
+    }
+
+    pub(crate) fn from_postfix(
+        postfix_regex: VecDeque<RegexSymbol>,
+        ignore_case: bool,
+        cache_capacity: usize,
+        limits: SizeLimits,
+        construction: NfaConstruction,
+    ) -> Result<Regex, BuildError> {
+        // The trie for an alternation of literals has at most as many nodes as the NFA would
+        limits.check_nfa(std::slice::from_ref(&postfix_regex))?;
+
+        // A single literal is better off with the prefilter, which gets to skip straight to it
+        if let Some(literals) = literals::alternatives(&postfix_regex) {
+            if literals.len() > 1 {
+                return Ok(Regex {
+                    engine: Arc::new(Engine::Literals(AhoCorasick::new(&literals, ignore_case))),
+                });
+            }
+        }
+
+        let automata = Automata::new(
+            postfix_regex,
+            ignore_case,
+            cache_capacity,
+            limits,
+            construction,
+        )?;
+
+        return Ok(Regex {
+            engine: Arc::new(Engine::Automata(Box::new(automata))),
+        });
+    }
+
+    // Matches the query exactly as written, without treating any of its characters as operators
+    pub fn fixed_string(literal: &str, ignore_case: bool) -> Result<Regex, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .build_fixed_strings(&[literal]);
+    }
+
+    // Matches any of the literals exactly as written
+    pub fn fixed_strings<S: AsRef<str>>(
+        literals: &[S],
+        ignore_case: bool,
+    ) -> Result<Regex, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .build_fixed_strings(literals);
+    }
+
+    pub(crate) fn from_literals(
+        literals: Vec<String>,
+        ignore_case: bool,
+        cache_capacity: usize,
+        limits: SizeLimits,
+        construction: NfaConstruction,
+    ) -> Result<Regex, BuildError> {
+        let mut postfix_regexes: Vec<VecDeque<RegexSymbol>> = literals
+            .iter()
+            .map(|literal| literal_postfix(literal))
+            .collect();
+
+        // Neither the Horspool table nor the Aho-Corasick trie is any bigger than the NFA for the literals
+        limits.check_nfa(&postfix_regexes)?;
+
+        if literals.len() != 1 {
+            return Ok(Regex {
+                engine: Arc::new(Engine::Literals(AhoCorasick::new(&literals, ignore_case))),
+            });
+        }
+
+        // Ignoring case outside of ASCII needs the full case folding the NFA does, so give it the
+        // postfix regex for the literal directly rather than escaping it all just to parse it again.
+        // That includes some ASCII letters too, e.g. k also matches the Kelvin sign.
+        let needs_full_folding = literals[0].chars().any(|c| {
+            simple_case_fold_set(c)
+                .iter()
+                .any(|folded| !folded.is_ascii())
+        });
+
+        if ignore_case && needs_full_folding {
+            let automata = Automata::new(
+                postfix_regexes.pop().unwrap(),
+                ignore_case,
+                cache_capacity,
+                limits,
+                construction,
+            )?;
+
+            return Ok(Regex {
+                engine: Arc::new(Engine::Automata(Box::new(automata))),
+            });
+        }
+
+        return Ok(Regex {
+            engine: Arc::new(Engine::Literal(Horspool::new(&literals[0], ignore_case))),
+        });
+    }
+
+    // Matches any text in the DFA's language, e.g. one put together from others with CompleteDfa::intersection
+    pub fn from_complete_dfa(dfa: &CompleteDfa) -> Regex {
+        let mut nfa: Graph<AutomataState, AutomataLabel> = Graph::new();
+        let handle = dfa.add_to_nfa(&mut nfa);
+
+        nfa.get_node_data_mut(&handle.get_accept_state())
+            .unwrap()
+            .mark_as_accepting_pattern(0);
+
+        let automata = Automata::from_nfa(
+            None,
+            handle,
+            nfa,
+            DEFAULT_CACHE_CAPACITY,
+            SizeLimits::default(),
+        );
+
+        return Regex {
+            engine: Arc::new(Engine::Automata(Box::new(automata))),
+        };
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        return match self.engine.as_ref() {
+            Engine::Automata(automata) => automata.is_match(haystack),
+            Engine::Literal(horspool) => horspool.find(haystack.as_bytes()).is_some(),
+            Engine::Literals(aho_corasick) => aho_corasick.find(haystack).is_some(),
+        };
+    }
+
+    // Finds the leftmost-longest match in the haystack
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        return self.find_at(haystack, 0);
+    }
+
+    // Finds the leftmost-longest match that starts at or after the given byte offset
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        let (s, e) = match self.engine.as_ref() {
+            Engine::Automata(automata) => automata.find_at(haystack, start)?,
+            Engine::Literal(horspool) => {
+                let s = start + horspool.find(&haystack.as_bytes()[start..])?;
+
+                (s, s + horspool.get_needle().len())
+            }
+            Engine::Literals(aho_corasick) => {
+                let (s, e, literal) = aho_corasick.find(&haystack[start..])?;
+
+                return Some(Match::with_literal(haystack, start + s, start + e, literal));
+            }
+        };
+
+        return Some(Match::new(haystack, s, e));
+    }
+
+    // Iterates over every non-overlapping leftmost-longest match in the haystack, from left to right.
+    // An empty match is never reported straight after the end of the previous match.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        return Matches::new(self, haystack, false);
+    }
+
+    // Iterates over the longest match starting at each position in the haystack, so the matches may overlap,
+    // e.g. "aa" is found 3 times in "aaaa" rather than twice.
+    pub fn find_overlapping_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        return Matches::new(self, haystack, true);
+    }
+
+    // Starts a search over a line that'll be fed in a piece at a time
+    pub(crate) fn line_search(&self) -> LineSearch<'_> {
+        return LineSearch::new(self);
+    }
+
+    pub fn is_using_dfa(&self) -> bool {
+        return match self.engine.as_ref() {
+            Engine::Automata(automata) => !automata.dfa_exhausted.load(Ordering::Relaxed),
+            Engine::Literal(_) | Engine::Literals(_) => false,
+        };
+    }
+
+    // Returns the literal with the given index, when the query is an alternation of literals
+    pub fn get_literal(&self, index: usize) -> Option<&str> {
+        return match self.engine.as_ref() {
+            Engine::Literals(aho_corasick) => aho_corasick.get_literal(index),
+            _ => None,
+        };
+    }
+}
+
+impl Automata {
+    fn new(
+        postfix_regex: VecDeque<RegexSymbol>,
+        ignore_case: bool,
+        cache_capacity: usize,
+        limits: SizeLimits,
+        construction: NfaConstruction,
+    ) -> Result<Automata, BuildError> {
+        // The literals are only exact when case matters, otherwise they'd each stand for their whole fold set
+        let prefilter = if ignore_case {
+            None
+        } else {
+            Prefilter::new(&literals::extract(&postfix_regex))
+        };
+
+        let (handle, nfa) =
+            build_multi_nfa_with(vec![postfix_regex], ignore_case, construction, limits)?;
+
+        return Ok(Automata::from_nfa(
+            prefilter,
+            handle,
+            nfa,
+            cache_capacity,
+            limits,
+        ));
+    }
+
+    fn from_nfa(
+        prefilter: Option<Prefilter>,
+        handle: AutomataComponent,
+        nfa: Graph<AutomataState, AutomataLabel>,
+        cache_capacity: usize,
+        limits: SizeLimits,
+    ) -> Automata {
+        let (reverse_handle, reverse_nfa) = reverse_nfa(&handle, &nfa);
+
+        let nfa = Arc::new(nfa);
+        let reverse_nfa = Arc::new(reverse_nfa);
+
+        let pike_vm = PikeVm::new(&handle, nfa.clone());
+
+        let lazy_dfa = |handle, nfa, kind| {
+            return LazyDfa::new(handle, nfa, kind, cache_capacity)
+                .with_limits(limits.dfa_states, limits.dfa_transitions);
+        };
+
+        let dfa = lazy_dfa(handle, nfa.clone(), SearchKind::Earliest);
+        let forward_dfa = lazy_dfa(handle, nfa, SearchKind::LeftmostLongest);
+        let reverse_dfa = lazy_dfa(reverse_handle, reverse_nfa, SearchKind::Anchored);
+
+        return Automata {
+            prefilter,
+            dfas: Pool::new(LazyDfas {
+                dfa,
+                forward_dfa,
+                reverse_dfa,
+            }),
+            pike_vm,
+            dfa_exhausted: AtomicBool::new(false),
+        };
+    }
+
+    fn is_match(&self, haystack: &str) -> bool {
+        let haystack = match self.find_candidate(haystack, 0) {
+            Some(candidate) => &haystack[candidate..],
+            None => return false,
+        };
+
+        if !self.dfa_exhausted.load(Ordering::Relaxed) {
+            match self.dfas.get().dfa.try_is_match(haystack) {
+                Ok(result) => return result,
+                Err(CacheExhausted) => self.dfa_exhausted.store(true, Ordering::Relaxed),
+            }
+        }
+
+        return self.pike_vm.is_match(haystack);
+    }
+
+    fn find_at(&self, haystack: &str, start: usize) -> Option<(usize, usize)> {
+        let start = self.find_candidate(haystack, start)?;
+        let rest = &haystack[start..];
+        let offset = |(s, e): (usize, usize)| (start + s, start + e);
+
+        if !self.dfa_exhausted.load(Ordering::Relaxed) {
+            match self.find_with_dfa(rest) {
+                Ok(result) => return result.map(offset),
+                Err(CacheExhausted) => self.dfa_exhausted.store(true, Ordering::Relaxed),
+            }
+        }
+
+        return self.pike_vm.find(rest).map(offset);
+    }
+
+    // Returns the first position at or after start where a match could begin
+    fn find_candidate(&self, haystack: &str, start: usize) -> Option<usize> {
+        return match &self.prefilter {
+            Some(prefilter) => Some(start + prefilter.find_candidate(&haystack[start..])?),
+            None => Some(start),
+        };
+    }
+
+    fn find_with_dfa(&self, haystack: &str) -> Result<Option<(usize, usize)>, CacheExhausted> {
+        // The forward DFA knows where the match ends, but it merges every attempt that got there
+        // so it's lost where the match started
+        let mut dfas = self.dfas.get();
+
+        let end = match dfas.forward_dfa.try_find_end(haystack)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+
+        // Nothing can match further left than the leftmost match, so the furthest left the reversed
+        // pattern matches going backwards from the end has to be where it starts
+        let start = dfas
+            .reverse_dfa
+            .try_rfind_start(&haystack[..end])?
+            .expect("Error - Reverse DFA should always find the start of a match");
+
+        return Ok(Some((start, end)));
+    }
+}
+
+// A search over a line that arrives a piece at a time, for stream.rs. Only the lazy DFA can carry on from where
+// the last piece left off, so the other engines, and the Pike VM once the DFA's given up, get the whole line at the
+// end instead. So does a pattern with a prefilter, since most lines are usually missing its literals and those get
+// thrown away without the DFA ever seeing them, which is far quicker than stepping through them a char at a time.
+pub(crate) struct LineSearch<'r> {
+    regex: &'r Regex,
+    // The lazy DFA the line's running on and its state after the pieces so far, or None when the line's left for
+    // the end
+    dfa: Option<(PoolGuard<'r, LazyDfas>, StateId)>,
+    flushes_before: usize,
+    matched: bool,
+}
+
+impl<'r> LineSearch<'r> {
+    fn new(regex: &'r Regex) -> LineSearch<'r> {
+        let mut search = LineSearch {
+            regex,
+            dfa: None,
+            flushes_before: 0,
+            matched: false,
+        };
+
+        if let Engine::Automata(automata) = regex.engine.as_ref() {
+            if automata.prefilter.is_none() && !automata.dfa_exhausted.load(Ordering::Relaxed) {
+                let mut dfas = automata.dfas.get();
+                let start = dfas.dfa.start_state();
+
+                search.flushes_before = dfas.dfa.cache_flushes();
+                search.matched = dfas.dfa.is_accepting(start);
+                search.dfa = Some((dfas, start));
+            }
+        }
+
+        return search;
+    }
+
+    // Whether there's a match in the line so far, once it's known there is
+    pub(crate) fn feed(&mut self, piece: &str) -> bool {
+        let (automata, (dfas, state)) = match (self.regex.engine.as_ref(), &mut self.dfa) {
+            (Engine::Automata(automata), Some(dfa)) if !self.matched => (automata, dfa),
+            _ => return self.matched,
+        };
+
+        for c in piece.chars() {
+            *state = dfas.dfa.next_state(*state, c);
+
+            if dfas.dfa.is_accepting(*state) {
+                self.matched = true;
+                break;
+            }
+        }
+
+        // Same as try_is_match, the DFA's no good for this pattern if it doesn't fit in the cache
+        if dfas.dfa.cache_flushes() - self.flushes_before > MAX_CACHE_FLUSHES {
+            automata.dfa_exhausted.store(true, Ordering::Relaxed);
+            self.dfa = None;
+        }
+
+        return self.matched;
+    }
+
+    // Whether the line has a match, given the whole of it
+    pub(crate) fn finish(&mut self, line: &str) -> bool {
+        if self.dfa.is_none() && !self.matched {
+            self.matched = self.regex.is_match(line);
+        }
+
+        return self.matched;
+    }
+}
+
+pub struct Matches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    overlapping: bool,
+    // Where the next search starts from, once this is past the end of the haystack we're done
+    next_start: usize,
+    last_match_end: Option<usize>,
+}
+
+impl<'r, 'h> Matches<'r, 'h> {
+    fn new(regex: &'r Regex, haystack: &'h str, overlapping: bool) -> Matches<'r, 'h> {
+        return Matches {
+            regex,
+            haystack,
+            overlapping,
+            next_start: 0,
+            last_match_end: None,
+        };
+    }
+
+    // The byte offset of the character after the one at the given position
+    fn next_char_boundary(&self, position: usize) -> usize {
+        return match self.haystack[position..].chars().next() {
+            Some(c) => position + c.len_utf8(),
+            None => position + 1,
+        };
+    }
+}
+
+impl<'r, 'h> Iterator for Matches<'r, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        while self.next_start <= self.haystack.len() {
+            let found = self.regex.find_at(self.haystack, self.next_start)?;
+
+            if self.overlapping {
+                self.next_start = self.next_char_boundary(found.get_start());
+
+                return Some(found);
+            }
+
+            if found.is_empty() {
+                // Step past the empty match, otherwise we'd just keep finding it
+                self.next_start = self.next_char_boundary(found.get_end());
+
+                // An empty match right where the previous match ended doesn't count
+                if self.last_match_end == Some(found.get_end()) {
+                    continue;
+                }
+            } else {
+                self.next_start = found.get_end();
+            }
+
+            self.last_match_end = Some(found.get_end());
+
+            return Some(found);
+        }
+
+        return None;
+    }
+}
+
+impl<'h> Match<'h> {
+    pub fn new(haystack: &'h str, start: usize, end: usize) -> Match<'h> {
+        return Match {
+            haystack,
+            start,
+            end,
+            literal: None,
+        };
+    }
+
+    fn with_literal(haystack: &'h str, start: usize, end: usize, literal: usize) -> Match<'h> {
+        return Match {
+            literal: Some(literal),
+            ..Match::new(haystack, start, end)
+        };
+    }
+
+    pub fn get_start(&self) -> usize {
+        return self.start;
+    }
+
+    pub fn get_end(&self) -> usize {
+        return self.end;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.start == self.end;
+    }
+
+    // Which of the literals matched, in the order they appear in the query. Only known when the query is an
+    // alternation of literals, see Regex::get_literal.
+    pub fn get_literal_index(&self) -> Option<usize> {
+        return self.literal;
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        return &self.haystack[self.start..self.end];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{nfa::build_nfa, postfixer};
+
+    fn pike_vm(query: &str) -> PikeVm {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+
+        return PikeVm::new(&handle, Arc::new(nfa));
+    }
+
+    #[test]
+    fn given_pattern_whose_dfa_fits_in_the_cache_when_matching_should_keep_using_the_dfa() {
+        let regex = Regex::new("(you)|(us)+", false).unwrap();
+
+        assert!(regex.is_match("Are you nobody, too?"));
+        assert!(!regex.is_match("How public, like a frog"));
+        assert!(regex.is_using_dfa());
+    }
+
+    #[test]
+    fn given_pattern_whose_dfa_exceeds_the_cache_when_matching_should_fall_back_to_the_pike_vm() {
+        let query = format!("(a|b)*a{}c", "(a|b)".repeat(10));
+        let regex = Regex::with_cache_capacity(&query, false, 1024).unwrap();
+
+        let haystack: String = (0..1024)
+            .map(|i: u32| format!("{:010b}", i).replace('0', "a").replace('1', "b"))
+            .collect();
+
+        // Leading c so the prefilter doesn't reject the line before the DFA gets to see it
+        assert!(!regex.is_match(&format!("c{}", haystack)));
+        assert!(!regex.is_using_dfa());
+
+        assert!(regex.is_match(&format!("{}c", haystack)));
+        assert!(!regex.is_match("bbbbbbbbbbbbc"));
+    }
+
+    #[test]
+    fn given_patterns_when_finding_should_return_the_same_leftmost_longest_match_as_the_pike_vm() {
+        let examples = [
+            ("ab|bcde", "abcde", Some((0, 2))),
+            ("abcd|c", "abcd", Some((0, 4))),
+            ("a+", "baaab", Some((1, 4))),
+            ("(a|b)*c", "xxababcab", Some((2, 7))),
+            ("b*", "abbb", Some((0, 0))),
+            ("(you)|(us)", "They'd banish us, you know.", Some((14, 16))),
+            ("é+", "caféé!", Some((3, 7))),
+            ("z", "abc", None),
+        ];
+
+        for (query, haystack, answer) in examples {
+            let regex = Regex::new(query, false).unwrap();
+
+            let found = regex.find(haystack).map(|m| (m.get_start(), m.get_end()));
+
+            assert_eq!(found, answer);
+            assert_eq!(pike_vm(query).find(haystack), answer);
+        }
+    }
+
+    #[test]
+    fn given_patterns_when_iterating_over_matches_should_return_every_non_overlapping_match() {
+        let examples = [
+            (
+                "(you)|(us)",
+                "you, us and you",
+                vec![(0, 3), (5, 7), (12, 15)],
+            ),
+            ("aa", "aaaaa", vec![(0, 2), (2, 4)]),
+            ("a*", "baaab", vec![(0, 0), (1, 4), (5, 5)]),
+            ("b*", "", vec![(0, 0)]),
+            ("é?", "éx", vec![(0, 2), (3, 3)]),
+            ("z", "abc", vec![]),
+        ];
+
+        for (query, haystack, answer) in examples {
+            let regex = Regex::new(query, false).unwrap();
+
+            let found: Vec<(usize, usize)> = regex
+                .find_iter(haystack)
+                .map(|m| (m.get_start(), m.get_end()))
+                .collect();
+
+            assert_eq!(found, answer);
+        }
+    }
+
+    #[test]
+    fn given_patterns_when_iterating_over_overlapping_matches_should_return_the_longest_match_at_every_start(
+    ) {
+        let examples = [
+            ("aa", "aaaa", vec![(0, 2), (1, 3), (2, 4)]),
+            ("(ab)+", "ababx", vec![(0, 4), (2, 4)]),
+            ("z", "abc", vec![]),
+        ];
+
+        for (query, haystack, answer) in examples {
+            let regex = Regex::new(query, false).unwrap();
+
+            let found: Vec<(usize, usize)> = regex
+                .find_overlapping_iter(haystack)
+                .map(|m| (m.get_start(), m.get_end()))
+                .collect();
+
+            assert_eq!(found, answer);
+        }
+    }
+
+    #[test]
+    fn given_pattern_with_required_literals_when_matching_should_give_the_same_answers_as_without_the_prefilter(
+    ) {
+        let examples = [
+            (
+                "ERROR (disk|net)",
+                "12:00 ERROR ERROR net down",
+                Some((12, 21)),
+            ),
+            ("ERROR (disk|net)", "12:00 ERROR cpu hot", None),
+            ("(a|b)*failed", "xxabfailed", Some((2, 10))),
+            ("(a|b)*failed", "xxabfaile", None),
+        ];
+
+        for (query, haystack, answer) in examples {
+            let regex = Regex::new(query, false).unwrap();
+
+            match regex.engine.as_ref() {
+                Engine::Automata(automata) => assert!(automata.prefilter.is_some()),
+                _ => panic!("Error - Expected an automata based engine"),
+            }
+            assert_eq!(regex.is_match(haystack), answer.is_some());
+            assert_eq!(
+                regex.find(haystack).map(|m| (m.get_start(), m.get_end())),
+                answer
+            );
+        }
+    }
+
+    #[test]
+    fn given_line_missing_required_literal_when_searching_a_line_at_a_time_should_never_step_the_dfa(
+    ) {
+        let regex = Regex::new("ERROR (disk|net)", false).unwrap();
+        let cached_states = || match regex.engine.as_ref() {
+            Engine::Automata(automata) => automata.dfas.get().dfa.num_of_cached_states(),
+            _ => panic!("Error - Expected an automata based engine"),
+        };
+        let search_line = |pieces: &[&str]| {
+            let mut search = regex.line_search();
+
+            for piece in pieces {
+                search.feed(piece);
+            }
+
+            return search.finish(&pieces.concat());
+        };
+
+        let before = cached_states();
+
+        assert!(!search_line(&["12:00 WARN ", "net slow"]));
+        assert_eq!(cached_states(), before);
+
+        assert!(search_line(&["12:00 ERROR ", "net down"]));
+        assert!(cached_states() > before);
+    }
+
+    #[test]
+    fn given_fixed_strings_when_finding_should_treat_operators_as_plain_characters() {
+        let examples = [
+            ("a(b|c)*", false, "xa(b|c)*y", vec![(1, 8)]),
+            ("(you)|(us)", false, "(you)|(us) and you", vec![(0, 10)]),
+            ("RuSt", true, "Rust, trust me", vec![(0, 4), (7, 11)]),
+            ("ΣΟΦΙΑ", true, "σοφια ΣΟΦΙΑ", vec![(0, 10), (11, 21)]),
+            // The Kelvin sign and long s are 3 and 2 bytes, but fold to plain ASCII letters
+            ("ok", true, "OK o\u{212A}", vec![(0, 2), (3, 7)]),
+            ("s", true, "S\u{017F}", vec![(0, 1), (1, 3)]),
+            ("abc", false, "ab", vec![]),
+        ];
+
+        for (literal, ignore_case, haystack, answer) in examples {
+            let regex = Regex::fixed_string(literal, ignore_case).unwrap();
+
+            let found: Vec<(usize, usize)> = regex
+                .find_iter(haystack)
+                .map(|m| (m.get_start(), m.get_end()))
+                .collect();
+
+            assert_eq!(found, answer);
+            assert_eq!(regex.is_match(haystack), !answer.is_empty());
+        }
+    }
+
+    #[test]
+    fn given_alternation_of_literals_when_finding_should_report_which_literal_matched() {
+        let regex = Regex::new("(you)|(us)|(nobody)", false).unwrap();
+        let haystack = "I'm nobody! Who are you? Are you nobody, too? Then there's a pair of us";
+
+        assert!(!regex.is_using_dfa());
+
+        let found: Vec<(&str, &str)> = regex
+            .find_iter(haystack)
+            .map(|m| {
+                (
+                    m.as_str(),
+                    regex.get_literal(m.get_literal_index().unwrap()).unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("nobody", "nobody"),
+                ("you", "you"),
+                ("you", "you"),
+                ("nobody", "nobody"),
+                ("us", "us")
+            ]
+        );
+
+        let regex = Regex::new("rust|trust", true).unwrap();
+
+        let m = regex.find("TRUST me").unwrap();
+        assert_eq!(
+            (m.get_start(), m.get_end(), m.get_literal_index()),
+            (0, 5, Some(1))
+        );
+    }
+
+    #[test]
+    fn given_many_queries_when_finding_should_match_any_of_them() {
+        let regex = Regex::new_many(&["(a|e)+r", "you", "us"], false).unwrap();
+
+        let found: Vec<&str> = regex
+            .find_iter("Who are you? Then there's a pair of us")
+            .map(|m| m.as_str())
+            .collect();
+
+        assert_eq!(found, vec!["ar", "you", "er", "us"]);
+
+        // Nothing but literals gets the literal engine, so we still know which one it was
+        let regex = Regex::new_many(&["you", "us"], false).unwrap();
+        let m = regex.find("banish us, you know").unwrap();
+
+        assert_eq!(
+            regex.get_literal(m.get_literal_index().unwrap()),
+            Some("us")
+        );
+
+        let regex = Regex::new_many::<&str>(&[], false).unwrap();
+
+        assert!(!regex.is_match("anything"));
+    }
+
+    #[test]
+    fn given_many_fixed_strings_when_finding_should_match_them_literally() {
+        let regex = Regex::fixed_strings(&["a+", "(b)"], false).unwrap();
+
+        let found: Vec<&str> = regex.find_iter("aa+ (b) b").map(|m| m.as_str()).collect();
+
+        assert_eq!(found, vec!["a+", "(b)"]);
+    }
+
+    #[test]
+    fn given_match_when_getting_as_str_should_return_the_matched_text() {
+        let regex = Regex::new("ERROR (disk|net)", true).unwrap();
+
+        let found = regex.find("12:00 error Net down").unwrap();
+
+        assert_eq!(found.as_str(), "error Net");
+    }
+
+    #[test]
+    fn given_intersection_with_a_complement_when_finding_should_leave_out_what_the_complement_excludes(
+    ) {
+        let regex = Regex::new("(a|e|f|h|i|l|o|r|w)+&~(if|for|while)", false).unwrap();
+
+        let examples = [
+            ("while", Some("whil")),
+            ("if", Some("i")),
+            ("iffy", Some("iff")),
+            ("zzz", None),
+        ];
+
+        for (haystack, answer) in examples {
+            assert_eq!(
+                regex.find(haystack).map(|m| m.as_str()),
+                answer,
+                "{}",
+                haystack
+            );
+        }
+    }
+
+    #[test]
+    fn given_complement_when_matching_should_match_characters_the_pattern_never_mentions() {
+        let regex = Regex::new("x~(a*)y", false).unwrap();
+
+        assert!(regex.is_match("xéy"));
+        assert!(regex.is_match("xaby"));
+        assert!(!regex.is_match("xaay"));
+        assert!(!regex.is_match("xy"));
+
+        // Everything between the first x and the last y isn't just a's, so that's the leftmost-longest match
+        assert_eq!(regex.find("xaa xbay").map(|m| m.as_str()), Some("xaa xbay"));
+        assert_eq!(pike_vm("x~(a*)y").find("xaa xbay"), Some((0, 8)));
+        assert_eq!(regex.find("xaay xba").map(|m| m.as_str()), None);
+    }
+
+    #[test]
+    fn given_complete_dfa_when_searching_should_find_text_in_its_language() {
+        let words = CompleteDfa::new("(a|b)+", false).unwrap();
+        let ab = CompleteDfa::new("ab", false).unwrap();
+
+        let regex = Regex::from_complete_dfa(&words.difference(&ab));
+
+        assert_eq!(regex.find("ab").map(|m| m.as_str()), Some("a"));
+        assert_eq!(regex.find("xbba").map(|m| m.as_str()), Some("bba"));
+        assert!(!regex.is_match("xyz"));
+    }
+
+    #[test]
+    fn given_regex_when_shared_between_threads_should_give_every_thread_the_same_answers() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+
+        let regex = Regex::new("(a|b)*abb", false).unwrap();
+        let haystacks = ["xxababbyy", "abab", "bbabb", "aabbaabb"];
+        let expected: Vec<Option<(usize, usize)>> = haystacks
+            .iter()
+            .map(|h| regex.find(h).map(|m| (m.get_start(), m.get_end())))
+            .collect();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                // Cloning only clones the Arc, so every thread shares the one compiled regex
+                let regex = regex.clone();
+                let expected = &expected;
+
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        for (haystack, answer) in haystacks.iter().zip(expected) {
+                            let found = regex.find(haystack).map(|m| (m.get_start(), m.get_end()));
+
+                            assert_eq!(found, *answer);
+                        }
+                    }
+                });
+            }
+        });
+
+        assert!(regex.is_using_dfa());
+    }
+}
@@ -0,0 +1,236 @@
+/* Substring search used to skip over input that can't match before handing it to the automata.
+ *
+ * Like memchr, it looks for a single byte of the needle a machine word at a time, and only compares the whole
+ * needle where that byte turns up. The byte we look for is the one that's least likely to show up in ordinary
+ * text, since the fewer false candidates there are the longer the stretches we get to skip over at full speed.
+ */
+
+const WORD_SIZE: usize = std::mem::size_of::<u64>();
+const LO: u64 = 0x0101010101010101;
+const HI: u64 = 0x8080808080808080;
+
+pub struct Finder {
+    needle: Vec<u8>,
+    // Index in the needle of the byte we scan for
+    rare_index: usize,
+}
+
+impl Finder {
+    pub fn new(needle: &str) -> Finder {
+        let needle = needle.as_bytes().to_vec();
+
+        let rare_index = (0..needle.len())
+            .min_by_key(|i| byte_frequency_rank(needle[*i]))
+            .unwrap_or(0);
+
+        return Finder { needle, rare_index };
+    }
+
+    pub fn get_needle(&self) -> &[u8] {
+        return &self.needle;
+    }
+
+    // Returns the byte offset of the first occurrence of the needle in the haystack
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        if self.needle.is_empty() {
+            return Some(0);
+        }
+
+        if haystack.len() < self.needle.len() {
+            return None;
+        }
+
+        let rare_byte = self.needle[self.rare_index];
+        // Any occurrence of the needle has to leave room for the bytes before and after the rare byte
+        let last_candidate = haystack.len() - self.needle.len() + self.rare_index;
+
+        let mut at = self.rare_index;
+
+        while at <= last_candidate {
+            let found = at + memchr(rare_byte, &haystack[at..=last_candidate])?;
+            let start = found - self.rare_index;
+
+            if haystack[start..start + self.needle.len()] == self.needle[..] {
+                return Some(start);
+            }
+
+            at = found + 1;
+        }
+
+        return None;
+    }
+}
+
+// Boyer-Moore-Horspool, for when the whole query is a literal and there's no automata to hand over to.
+// The needle is compared right to left, and on a mismatch we shift by however far the byte under the end
+// of the needle is from its last occurrence in the needle, which skips most of the haystack for long needles.
+pub struct Horspool {
+    needle: Vec<u8>,
+    shift: Vec<usize>,
+    ignore_ascii_case: bool,
+}
+
+impl Horspool {
+    pub fn new(needle: &str, ignore_ascii_case: bool) -> Horspool {
+        let needle = needle.as_bytes().to_vec();
+        let mut shift = vec![needle.len().max(1); 256];
+
+        // The last byte's shift stays as it is, since shifting by 0 would never move past it
+        for (i, byte) in needle
+            .iter()
+            .enumerate()
+            .take(needle.len().saturating_sub(1))
+        {
+            let distance = needle.len() - 1 - i;
+
+            if ignore_ascii_case {
+                shift[byte.to_ascii_lowercase() as usize] = distance;
+                shift[byte.to_ascii_uppercase() as usize] = distance;
+            } else {
+                shift[*byte as usize] = distance;
+            }
+        }
+
+        return Horspool {
+            needle,
+            shift,
+            ignore_ascii_case,
+        };
+    }
+
+    pub fn get_needle(&self) -> &[u8] {
+        return &self.needle;
+    }
+
+    // Returns the byte offset of the first occurrence of the needle in the haystack
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        if self.needle.is_empty() {
+            return Some(0);
+        }
+
+        let last = self.needle.len() - 1;
+        let mut start = 0;
+
+        while start + last < haystack.len() {
+            let window = &haystack[start..=start + last];
+
+            let matched = if self.ignore_ascii_case {
+                window.eq_ignore_ascii_case(&self.needle)
+            } else {
+                window == &self.needle[..]
+            };
+
+            if matched {
+                return Some(start);
+            }
+
+            start += self.shift[haystack[start + last] as usize];
+        }
+
+        return None;
+    }
+}
+
+// Returns the index of the first occurrence of the byte in the haystack
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LO * byte as u64;
+    let mut chunks = haystack.chunks_exact(WORD_SIZE);
+    let mut offset = 0;
+
+    for chunk in chunks.by_ref() {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap()) ^ repeated;
+
+        // Sets the high bit of every byte in the word that's zero, i.e. that matched the byte we're after
+        if word.wrapping_sub(LO) & !word & HI != 0 {
+            return chunk.iter().position(|b| *b == byte).map(|i| offset + i);
+        }
+
+        offset += WORD_SIZE;
+    }
+
+    return chunks
+        .remainder()
+        .iter()
+        .position(|b| *b == byte)
+        .map(|i| offset + i);
+}
+
+// A rough guess at how common each byte is in text, the lower the rarer
+fn byte_frequency_rank(byte: u8) -> u8 {
+    return match byte {
+        b' ' | b'e' | b't' | b'a' | b'o' | b'i' | b'n' | b's' | b'r' | b'h' => 6,
+        b'l' | b'd' | b'c' | b'u' | b'm' | b'w' | b'f' | b'g' | b'y' | b'p' => 5,
+        b'a'..=b'z' | b'0'..=b'9' => 4,
+        b'\n' | b'\t' | b'.' | b',' | b'-' | b'_' | b'/' | b':' | b'=' => 3,
+        b'A'..=b'Z' => 2,
+        0x80..=0xFF => 1,
+        _ => 0,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_bytes_in_and_out_of_the_haystack_when_calling_memchr_should_return_the_first_occurrence(
+    ) {
+        let haystack = b"the quick brown fox jumps over the lazy dog";
+
+        assert_eq!(memchr(b't', haystack), Some(0));
+        assert_eq!(memchr(b'x', haystack), Some(18));
+        assert_eq!(memchr(b'g', haystack), Some(42));
+        assert_eq!(memchr(b'!', haystack), None);
+        assert_eq!(memchr(b'a', b""), None);
+    }
+
+    #[test]
+    fn given_needles_when_finding_should_return_the_first_occurrence() {
+        let haystack = "ERROR disk full, ERROR net down, WARN disk slow".as_bytes();
+
+        let examples = [
+            ("ERROR ", Some(0)),
+            ("net", Some(23)),
+            ("WARN disk slow", Some(33)),
+            ("disk slow", Some(38)),
+            ("FATAL", None),
+            ("slow!", None),
+            ("", Some(0)),
+        ];
+
+        for (needle, answer) in examples {
+            assert_eq!(Finder::new(needle).find(haystack), answer);
+        }
+    }
+
+    #[test]
+    fn given_needle_longer_than_haystack_when_finding_should_not_find_it() {
+        assert_eq!(Finder::new("longer").find(b"short"), None);
+        assert_eq!(Horspool::new("longer", false).find(b"short"), None);
+    }
+
+    #[test]
+    fn given_needles_when_finding_with_horspool_should_return_the_first_occurrence() {
+        let haystack = "ERROR disk full, ERROR net down, WARN disk slow".as_bytes();
+
+        let examples = [
+            ("ERROR ", false, Some(0)),
+            ("net", false, Some(23)),
+            ("disk slow", false, Some(38)),
+            ("error NET", false, None),
+            ("error NET", true, Some(17)),
+            ("Warn Disk", true, Some(33)),
+            ("FATAL", true, None),
+            ("", false, Some(0)),
+        ];
+
+        for (needle, ignore_ascii_case, answer) in examples {
+            assert_eq!(
+                Horspool::new(needle, ignore_ascii_case).find(haystack),
+                answer,
+                "{}",
+                needle
+            );
+        }
+    }
+}
@@ -1,55 +1,235 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
 
 use crate::automata::{AutomataComponent, AutomataLabel, AutomataState};
+use crate::builder::{BuildError, SizeLimits};
+use crate::case_fold::simple_case_fold_set;
+use crate::complete_dfa::CompleteDfa;
+use crate::glushkov;
 use crate::graph::Graph;
 use crate::regex::RegexSymbol;
 
+// Glushkov's construction is only picked by Auto while it adds at most this many edges.
+// In glushkov's benchmark (long alternations, (a|b)*a(a|b)(a|b)..., nested stars), Glushkov's construction was
+// never slower. It built the DFA 1.2 to 4 times faster and searched up to 100 times faster. That includes
+// (w1|w2|...|w2000)*x, which has 4 million edges against Thompson's 24 thousand, so it's only the memory for all
+// those edges that's worth guarding against.
+const GLUSHKOV_EDGE_LIMIT: usize = 1_000_000;
+
+// How to build the NFA for each pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NfaConstruction {
+    Thompson,
+    // No empty edges between characters, see glushkov. Patterns with & or ~ still get Thompson's construction.
+    Glushkov,
+    // Glushkov's construction unless it would need too many edges
+    #[default]
+    Auto,
+}
+
+impl NfaConstruction {
+    // The construction to actually use for the pattern
+    pub fn choose(
+        self,
+        postfix_regex: &VecDeque<RegexSymbol>,
+        ignore_case: bool,
+    ) -> NfaConstruction {
+        if !glushkov::is_supported(postfix_regex) {
+            return NfaConstruction::Thompson;
+        }
+
+        return match self {
+            NfaConstruction::Auto => {
+                if glushkov::estimate_edges(postfix_regex, ignore_case) <= GLUSHKOV_EDGE_LIMIT {
+                    NfaConstruction::Glushkov
+                } else {
+                    NfaConstruction::Thompson
+                }
+            }
+            construction => construction,
+        };
+    }
+}
+
 // Using Thompson construction of the NFA from postfix regex
 // The final NFA will have exactly one initial state and one final accepting state
 // Link: https://en.wikipedia.org/wiki/Thompson%27s_construction
+//
+// When ignoring case each literal gets an edge for every character in its case fold set, so the
+// automata built from the NFA never have to think about case at all.
+//
+// The limits are only checked for & and ~, whose DFAs get built along with the NFA. Check the rest with
+// SizeLimits::check_nfa before building it.
 pub fn build_nfa(
     postfix_regex: VecDeque<RegexSymbol>,
-) -> (AutomataComponent, Graph<AutomataState, AutomataLabel>) {
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<(AutomataComponent, Graph<AutomataState, AutomataLabel>), BuildError> {
+    return build_multi_nfa(vec![postfix_regex], ignore_case, limits);
+}
+
+// Builds one NFA that matches any of the patterns, by joining them up under a shared start state.
+// Each pattern's own accept state is tagged with the pattern's index, so any automata built from this
+// one can tell which of the patterns it's matched from the NFA states it's in.
+pub fn build_multi_nfa(
+    postfix_regexes: Vec<VecDeque<RegexSymbol>>,
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<(AutomataComponent, Graph<AutomataState, AutomataLabel>), BuildError> {
+    return build_multi_nfa_with(
+        postfix_regexes,
+        ignore_case,
+        NfaConstruction::Thompson,
+        limits,
+    );
+}
+
+// The same as build_multi_nfa, but building each pattern with the construction given
+pub fn build_multi_nfa_with(
+    postfix_regexes: Vec<VecDeque<RegexSymbol>>,
+    ignore_case: bool,
+    construction: NfaConstruction,
+    limits: SizeLimits,
+) -> Result<(AutomataComponent, Graph<AutomataState, AutomataLabel>), BuildError> {
     let mut nfa: Graph<AutomataState, AutomataLabel> = Graph::new();
+
+    if postfix_regexes.len() == 1 {
+        let postfix_regex = postfix_regexes.into_iter().next().unwrap();
+        let result = compile_pattern(&mut nfa, postfix_regex, ignore_case, construction, limits)?;
+
+        // Mark final state as accepting
+        nfa.get_node_data_mut(&result.get_accept_state())
+            .unwrap()
+            .mark_as_accepting_pattern(0);
+
+        return Ok((result, nfa));
+    }
+
+    let start = nfa.add_node(AutomataState::new(false));
+    let accept = nfa.add_node(AutomataState::new(true));
+
+    for (pattern, postfix_regex) in postfix_regexes.into_iter().enumerate() {
+        let component =
+            compile_pattern(&mut nfa, postfix_regex, ignore_case, construction, limits)?;
+
+        nfa.get_node_data_mut(&component.get_accept_state())
+            .unwrap()
+            .mark_as_accepting_pattern(pattern);
+
+        nfa.add_edge(start, component.get_start_state(), AutomataLabel::new(None));
+        nfa.add_edge(
+            component.get_accept_state(),
+            accept,
+            AutomataLabel::new(None),
+        );
+    }
+
+    return Ok((AutomataComponent::new(start, accept), nfa));
+}
+
+// How many nodes build_multi_nfa will add for the patterns, without having to build them.
+// Concatenation just joins its operands up, every other symbol adds a start and an accept state.
+// The Glushkov construction only adds one node per character and two more, so this is an upper bound for it too.
+// & and ~ actually add a state for every state of their DFA, which can't be known without building it, so they're
+// checked against the limits as they're built instead.
+pub fn count_nfa_nodes(postfix_regexes: &[VecDeque<RegexSymbol>]) -> usize {
+    let nodes: usize = postfix_regexes
+        .iter()
+        .map(|postfix_regex| {
+            let nodes: usize = postfix_regex
+                .iter()
+                .map(|symbol| match symbol {
+                    RegexSymbol::Concat => 0,
+                    _ => 2,
+                })
+                .sum();
+
+            // The empty pattern still gets a start and an accept state
+            return nodes.max(2);
+        })
+        .sum();
+
+    if postfix_regexes.len() == 1 {
+        return nodes;
+    }
+
+    return nodes + 2;
+}
+
+fn compile_pattern(
+    nfa: &mut Graph<AutomataState, AutomataLabel>,
+    postfix_regex: VecDeque<RegexSymbol>,
+    ignore_case: bool,
+    construction: NfaConstruction,
+    limits: SizeLimits,
+) -> Result<AutomataComponent, BuildError> {
+    // The empty pattern only matches the empty string, so it matches at every position
+    if postfix_regex.is_empty() {
+        let start = nfa.add_node(AutomataState::new(false));
+        let accept = nfa.add_node(AutomataState::new(false));
+
+        nfa.add_edge(start, accept, AutomataLabel::new(None));
+
+        return Ok(AutomataComponent::new(start, accept));
+    }
+
+    if construction.choose(&postfix_regex, ignore_case) == NfaConstruction::Glushkov {
+        if glushkov::estimate_edges(&postfix_regex, ignore_case) <= limits.nfa_edges {
+            return Ok(glushkov::compile_pattern(nfa, postfix_regex, ignore_case));
+        }
+
+        // Auto only asked for Glushkov's construction because it's quicker, Thompson's does just as well
+        if construction != NfaConstruction::Auto {
+            return Err(BuildError::TooManyNfaEdges {
+                limit: limits.nfa_edges,
+            });
+        }
+    }
+
     let mut component_stack: Vec<AutomataComponent> = Vec::new();
 
     for symbol in postfix_regex {
-        let component = compile(&mut nfa, &mut component_stack, symbol);
+        let component = compile(nfa, &mut component_stack, symbol, ignore_case, limits)?;
         component_stack.push(component);
     }
 
-    let result = component_stack.pop().unwrap();
-
-    // Mark final state as accepting
-    nfa.get_node_data(&result.get_accept_state())
-        .unwrap()
-        .borrow_mut()
-        .mark_as_accepting();
-
-    return (result, nfa);
+    return Ok(component_stack.pop().unwrap());
 }
 
 fn compile(
     nfa: &mut Graph<AutomataState, AutomataLabel>,
     component_stack: &mut Vec<AutomataComponent>,
     symbol: RegexSymbol,
-) -> AutomataComponent {
+    ignore_case: bool,
+    limits: SizeLimits,
+) -> Result<AutomataComponent, BuildError> {
     match symbol {
-        RegexSymbol::Optional => return compile_optional(nfa, component_stack),
-        RegexSymbol::Plus => return compile_plus(nfa, component_stack),
-        RegexSymbol::Star => return compile_star(nfa, component_stack),
-        RegexSymbol::Concat => return compile_concat(nfa, component_stack),
-        RegexSymbol::Alternation => return compile_alternation(nfa, component_stack),
-        RegexSymbol::Char(c) => return compile_character(nfa, c),
+        RegexSymbol::Optional => return Ok(compile_optional(nfa, component_stack)),
+        RegexSymbol::Plus => return Ok(compile_plus(nfa, component_stack)),
+        RegexSymbol::Star => return Ok(compile_star(nfa, component_stack)),
+        RegexSymbol::Concat => return Ok(compile_concat(nfa, component_stack)),
+        RegexSymbol::Alternation => return Ok(compile_alternation(nfa, component_stack)),
+        RegexSymbol::Intersection => return compile_intersection(nfa, component_stack, limits),
+        RegexSymbol::Complement => return compile_complement(nfa, component_stack, limits),
+        RegexSymbol::Char(c) => return Ok(compile_character(nfa, c, ignore_case)),
         _ => panic!("Error - Parenthesis should have been removed in postfixing stage!"),
     }
 }
 
-fn compile_character(nfa: &mut Graph<AutomataState, AutomataLabel>, c: char) -> AutomataComponent {
+fn compile_character(
+    nfa: &mut Graph<AutomataState, AutomataLabel>,
+    c: char,
+    ignore_case: bool,
+) -> AutomataComponent {
     let start = nfa.add_node(AutomataState::new(false));
     let accept = nfa.add_node(AutomataState::new(false));
 
-    nfa.add_edge(start, accept, AutomataLabel::new(Some(c)));
+    if ignore_case {
+        for folded in simple_case_fold_set(c) {
+            nfa.add_edge(start, accept, AutomataLabel::new(Some(folded)));
+        }
+    } else {
+        nfa.add_edge(start, accept, AutomataLabel::new(Some(c)));
+    }
 
     return AutomataComponent::new(start, accept);
 }
@@ -144,3 +324,112 @@ fn compile_alternation(
 
     return AutomataComponent::new(start, accept);
 }
+
+// Neither of these can be done on the NFA directly, so the operands get determinized and combined as DFAs, which
+// then go back into the NFA in their place. The operands' own states are left behind unused.
+// Determinizing can blow up exponentially, so each DFA is held to the DFA limits, and what's added back to the
+// NFA to the NFA limit.
+fn compile_intersection(
+    nfa: &mut Graph<AutomataState, AutomataLabel>,
+    component_stack: &mut Vec<AutomataComponent>,
+    limits: SizeLimits,
+) -> Result<AutomataComponent, BuildError> {
+    let right = component_stack.pop().unwrap();
+    let left = component_stack.pop().unwrap();
+
+    let left = CompleteDfa::from_nfa(&left, nfa, limits)?;
+    let right = CompleteDfa::from_nfa(&right, nfa, limits)?;
+
+    let component = left
+        .intersection_with_limits(&right, limits)?
+        .add_to_nfa(nfa);
+
+    return check_nfa_size(nfa, component, limits);
+}
+
+fn compile_complement(
+    nfa: &mut Graph<AutomataState, AutomataLabel>,
+    component_stack: &mut Vec<AutomataComponent>,
+    limits: SizeLimits,
+) -> Result<AutomataComponent, BuildError> {
+    let top = component_stack.pop().unwrap();
+
+    let component = CompleteDfa::from_nfa(&top, nfa, limits)?
+        .complement()
+        .add_to_nfa(nfa);
+
+    return check_nfa_size(nfa, component, limits);
+}
+
+fn check_nfa_size(
+    nfa: &Graph<AutomataState, AutomataLabel>,
+    component: AutomataComponent,
+    limits: SizeLimits,
+) -> Result<AutomataComponent, BuildError> {
+    if nfa.num_of_nodes() > limits.nfa_nodes {
+        return Err(BuildError::NfaTooBig {
+            limit: limits.nfa_nodes,
+        });
+    }
+
+    return Ok(component);
+}
+
+// Every character on an edge of the NFA, in sorted order. Unlike regex::get_alphabet this includes the
+// characters case folding adds, so it's the alphabet to build a DFA over.
+pub fn get_nfa_alphabet(nfa: &Graph<AutomataState, AutomataLabel>) -> Vec<char> {
+    let mut alphabet: BTreeSet<char> = BTreeSet::new();
+
+    for node in 0..nfa.num_of_nodes() {
+        for edge in nfa.outgoing_edges(node).unwrap() {
+            let label = nfa.get_edge_data(&edge).unwrap();
+
+            if let Some(c) = label.get_label() {
+                alphabet.insert(c);
+            }
+
+            // Every character outside the alphabet then behaves the same way, see get_other_char
+            if let Some(excluded) = label.get_excluded() {
+                alphabet.extend(excluded.iter());
+            }
+        }
+    }
+
+    return alphabet.into_iter().collect();
+}
+
+// A character that isn't in the alphabet, to stand in for all of them. Every character outside the alphabet
+// of an NFA goes along exactly the same edges, so a DFA only needs the one transition for all of them.
+pub fn get_other_char(alphabet: &[char]) -> char {
+    return (0..=char::MAX as u32)
+        .rev()
+        .filter_map(char::from_u32)
+        .find(|c| alphabet.binary_search(c).is_err())
+        .expect("Error - The alphabet can't have every character in it");
+}
+
+// Builds the NFA for the reversed language by flipping every edge and swapping the start and accept states.
+// Running this backwards over the input from where a match ends finds where it starts.
+pub fn reverse_nfa(
+    handle: &AutomataComponent,
+    nfa: &Graph<AutomataState, AutomataLabel>,
+) -> (AutomataComponent, Graph<AutomataState, AutomataLabel>) {
+    let mut reversed: Graph<AutomataState, AutomataLabel> = Graph::new();
+
+    for node in 0..nfa.num_of_nodes() {
+        reversed.add_node(AutomataState::new(node == handle.get_start_state()));
+    }
+
+    for node in 0..nfa.num_of_nodes() {
+        for edge in nfa.outgoing_edges(node).unwrap() {
+            let label = nfa.get_edge_data(&edge).unwrap().clone();
+            let target = nfa.traverse(edge).unwrap();
+
+            reversed.add_edge(target, node, label);
+        }
+    }
+
+    let handle = AutomataComponent::new(handle.get_accept_state(), handle.get_start_state());
+
+    return (handle, reversed);
+}
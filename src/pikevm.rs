@@ -0,0 +1,294 @@
+/* A Pike VM simulates the Thompson NFA directly instead of determinizing it first. It keeps a list of
+ * "threads", one per NFA state we could currently be in, and advances all of them in lock step with the
+ * input. Since the list is a set of NFA states it can never hold more than m entries, so a search over n
+ * characters takes O(n * m) time no matter how bad the equivalent DFA would be.
+ *
+ * The state lists are sparse sets (https://research.swtch.com/sparse), which give O(1) insert, membership
+ * and clear, and iterate in insertion order. That order matters since each thread also remembers where in
+ * the input its match attempt started. Threads are always added in order of their start position, so the
+ * first thread to reach a given NFA state is the one that started furthest left, and any later thread
+ * arriving at the same state can be dropped without losing anything. This gives us the start and end
+ * positions of the leftmost-longest match essentially for free.
+ *
+ * Link: https://swtch.com/~rsc/regexp/regexp2.html
+ */
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use crate::{
+    automata::{AutomataComponent, AutomataLabel, AutomataState, PatternID},
+    graph::{Graph, NodeIndex},
+};
+
+pub struct PikeVm {
+    nfa: Arc<Graph<AutomataState, AutomataLabel>>,
+    start: NodeIndex,
+    accept: NodeIndex,
+}
+
+struct SparseSet {
+    dense: Vec<NodeIndex>,
+    sparse: Vec<usize>,
+}
+
+// The set of NFA states we're in, along with the position each thread started matching from
+struct Threads {
+    set: SparseSet,
+    starts: Vec<usize>,
+}
+
+impl PikeVm {
+    pub fn new(
+        handle: &AutomataComponent,
+        nfa: Arc<Graph<AutomataState, AutomataLabel>>,
+    ) -> PikeVm {
+        return PikeVm {
+            nfa,
+            start: handle.get_start_state(),
+            accept: handle.get_accept_state(),
+        };
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        return self.search(haystack, true).is_some();
+    }
+
+    // Returns the byte range of the leftmost-longest match in the haystack
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+        return self.search(haystack, false);
+    }
+
+    // Returns every pattern with a match somewhere in the haystack, for an NFA built by nfa::build_multi_nfa
+    pub fn matching_patterns(&self, haystack: &str) -> BTreeSet<PatternID> {
+        let size = self.nfa.num_of_nodes();
+
+        let mut current = Threads::new(size);
+        let mut next = Threads::new(size);
+        let mut found: BTreeSet<PatternID> = BTreeSet::new();
+
+        let mut chars = haystack.char_indices();
+
+        loop {
+            // Start positions don't matter here, every thread is as good as any other
+            self.add_thread(&mut current, self.start, 0);
+
+            if current.set.contains(self.accept) {
+                for state in current.set.iter() {
+                    let data = self.nfa.get_node_data(state).unwrap();
+                    found.extend(data.get_patterns().iter());
+                }
+            }
+
+            let c = match chars.next() {
+                Some((_, c)) => c,
+                None => break,
+            };
+
+            self.step(&current, &mut next, c);
+
+            std::mem::swap(&mut current, &mut next);
+            next.set.clear();
+        }
+
+        return found;
+    }
+
+    fn search(&self, haystack: &str, stop_at_first_match: bool) -> Option<(usize, usize)> {
+        let size = self.nfa.num_of_nodes();
+
+        let mut current = Threads::new(size);
+        let mut next = Threads::new(size);
+        let mut matched: Option<(usize, usize)> = None;
+
+        let mut chars = haystack.char_indices().peekable();
+
+        loop {
+            let at = match chars.peek() {
+                Some((i, _)) => *i,
+                None => haystack.len(),
+            };
+
+            // Only start new attempts while we haven't found anything, any match found from here on
+            // would start further right than the one we already have
+            if matched.is_none() {
+                self.add_thread(&mut current, self.start, at);
+            }
+
+            if current.set.contains(self.accept) {
+                let start = current.starts[self.accept];
+
+                matched = Some((start, at));
+
+                if stop_at_first_match {
+                    break;
+                }
+
+                // Threads that started after this match can't produce a match further left
+                current.retain_started_by(start);
+            }
+
+            if current.set.is_empty() {
+                break;
+            }
+
+            let c = match chars.next() {
+                Some((_, c)) => c,
+                None => break,
+            };
+
+            self.step(&current, &mut next, c);
+
+            std::mem::swap(&mut current, &mut next);
+            next.set.clear();
+        }
+
+        return matched;
+    }
+
+    fn step(&self, current: &Threads, next: &mut Threads, c: char) {
+        for state in current.set.iter() {
+            let outgoing_edges = self.nfa.outgoing_edges(*state).unwrap();
+
+            for edge in outgoing_edges {
+                let data = self.nfa.get_edge_data(&edge).unwrap();
+
+                if data.matches(c) {
+                    let target = self.nfa.traverse(edge).unwrap();
+                    self.add_thread(next, target, current.starts[*state]);
+                }
+            }
+        }
+    }
+
+    // Adds the thread along with everything in its empty closure, again depth first like the DFA
+    fn add_thread(&self, threads: &mut Threads, state: NodeIndex, start: usize) {
+        let mut visit_stack: Vec<NodeIndex> = vec![state];
+
+        while let Some(current) = visit_stack.pop() {
+            if threads.set.contains(current) {
+                continue;
+            }
+
+            threads.set.insert(current);
+            threads.starts[current] = start;
+
+            let outgoing_edges = self.nfa.outgoing_edges(current).unwrap();
+
+            for edge in outgoing_edges {
+                let data = self.nfa.get_edge_data(&edge).unwrap();
+
+                if data.is_empty() {
+                    visit_stack.push(self.nfa.traverse(edge).unwrap());
+                }
+            }
+        }
+    }
+}
+
+impl SparseSet {
+    fn new(capacity: usize) -> SparseSet {
+        return SparseSet {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        };
+    }
+
+    fn contains(&self, value: NodeIndex) -> bool {
+        let index = self.sparse[value];
+
+        return index < self.dense.len() && self.dense[index] == value;
+    }
+
+    fn insert(&mut self, value: NodeIndex) {
+        self.sparse[value] = self.dense.len();
+        self.dense.push(value);
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.dense.is_empty();
+    }
+
+    fn iter(&self) -> std::slice::Iter<'_, NodeIndex> {
+        return self.dense.iter();
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+impl Threads {
+    fn new(capacity: usize) -> Threads {
+        return Threads {
+            set: SparseSet::new(capacity),
+            starts: vec![0; capacity],
+        };
+    }
+
+    fn retain_started_by(&mut self, position: usize) {
+        let kept: Vec<NodeIndex> = self
+            .set
+            .iter()
+            .filter(|state| self.starts[**state] <= position)
+            .copied()
+            .collect();
+
+        self.set.clear();
+
+        for state in kept {
+            self.set.insert(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder::SizeLimits, nfa::build_nfa, postfixer};
+
+    fn build(query: &str, ignore_case: bool) -> PikeVm {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, ignore_case, SizeLimits::unlimited()).unwrap();
+
+        return PikeVm::new(&handle, Arc::new(nfa));
+    }
+
+    #[test]
+    fn given_basic_patterns_when_matching_should_find_them_anywhere_in_the_haystack() {
+        let pike_vm = build("(you)|(us)", false);
+
+        assert!(pike_vm.is_match("I'm nobody! Who are you?"));
+        assert!(pike_vm.is_match("They'd banish us, you know."));
+        assert!(!pike_vm.is_match("How dreary to be somebody!"));
+    }
+
+    #[test]
+    fn given_patterns_when_finding_should_return_the_leftmost_longest_match() {
+        let examples = [
+            ("ab|bcde", "abcde", Some((0, 2))),
+            ("abcd|c", "abcd", Some((0, 4))),
+            ("a+", "baaab", Some((1, 4))),
+            ("(a|b)*c", "xxababcab", Some((2, 7))),
+            ("b*", "abbb", Some((0, 0))),
+            ("z", "abc", None),
+        ];
+
+        for (query, haystack, answer) in examples {
+            assert_eq!(build(query, false).find(haystack), answer);
+        }
+    }
+
+    #[test]
+    fn given_multi_byte_characters_when_finding_should_return_byte_offsets() {
+        let pike_vm = build("é+", false);
+
+        assert_eq!(pike_vm.find("caféé!"), Some((3, 7)));
+    }
+
+    #[test]
+    fn given_ignore_case_when_finding_should_match_regardless_of_case() {
+        let pike_vm = build("rUsT", true);
+
+        assert_eq!(pike_vm.find("Trust me."), Some((1, 5)));
+    }
+}
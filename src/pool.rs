@@ -0,0 +1,95 @@
+/* A compiled Regex can be shared between threads, but the lazy DFA's cache gets added to as it searches, so each
+ * search needs a cache to itself while it runs. The pool holds on to the caches that aren't being used, hands one
+ * out to each search and takes it back once the search is done. The lock is only held while taking a cache off
+ * the stack or putting one back, so a search never waits on another search, and there are never more caches than
+ * searches that have run at the same time.
+ *
+ * New caches are cloned from an empty template, rather than redoing the work of setting one up every time.
+ */
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Mutex, PoisonError},
+};
+
+pub(crate) struct Pool<T: Clone> {
+    template: T,
+    // Boxed so handing one out only moves a pointer, however big the cache is
+    stack: Mutex<Vec<Box<T>>>,
+}
+
+// Puts the value back in the pool when it's dropped
+pub(crate) struct PoolGuard<'p, T: Clone> {
+    pool: &'p Pool<T>,
+    value: Option<Box<T>>,
+}
+
+impl<T: Clone> Pool<T> {
+    pub(crate) fn new(template: T) -> Pool<T> {
+        return Pool {
+            template,
+            stack: Mutex::new(Vec::new()),
+        };
+    }
+
+    pub(crate) fn get(&self) -> PoolGuard<'_, T> {
+        // Nothing can be left half done while the lock is held, so a panic elsewhere doesn't matter to us
+        let value = self
+            .stack
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .unwrap_or_else(|| Box::new(self.template.clone()));
+
+        return PoolGuard {
+            pool: self,
+            value: Some(value),
+        };
+    }
+}
+
+impl<'p, T: Clone> Deref for PoolGuard<'p, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return self.value.as_ref().unwrap();
+    }
+}
+
+impl<'p, T: Clone> DerefMut for PoolGuard<'p, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        return self.value.as_mut().unwrap();
+    }
+}
+
+impl<'p, T: Clone> Drop for PoolGuard<'p, T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.pool
+                .stack
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_value_given_back_when_getting_again_should_reuse_it_rather_than_clone_the_template() {
+        let pool = Pool::new(Vec::<usize>::new());
+
+        pool.get().push(1);
+        assert_eq!(*pool.get(), vec![1]);
+
+        // Both are out at once, so the second has to be a fresh one
+        let first = pool.get();
+        let second = pool.get();
+
+        assert_eq!(*first, vec![1]);
+        assert!(second.is_empty());
+    }
+}
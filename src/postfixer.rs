@@ -2,12 +2,17 @@ use crate::regex::{OperatorType, RegexSymbol};
 use std::collections::VecDeque;
 
 pub fn transform(regex: String) -> Result<VecDeque<RegexSymbol>, String> {
+    let formatted = tokenize(regex)?;
+
+    return convert(formatted);
+}
+
+// Everything transform does before the shunting yard, i.e. the infix tokens with the concats filled in
+pub(crate) fn tokenize(regex: String) -> Result<Vec<RegexSymbol>, String> {
     let regex = check_start_and_end_chars(regex)?;
     let regex = check_for_illegal_operator_sequences(regex)?;
 
-    let formatted = format(regex)?;
-
-    return convert(formatted);
+    return format(regex);
 }
 
 fn format(regex: String) -> Result<Vec<RegexSymbol>, String> {
@@ -20,7 +25,7 @@ fn format(regex: String) -> Result<Vec<RegexSymbol>, String> {
         let current: char = c;
 
         if current == '\\' && !escape_flag {
-            if iter.peek() == None {
+            if iter.peek().is_none() {
                 // Need to check the trailing / here since doing it above would error on // when it shouldn't
                 return Err("Error - Pattern may not end with a trailing backslash".to_string());
             }
@@ -43,8 +48,10 @@ fn format(regex: String) -> Result<Vec<RegexSymbol>, String> {
             }
         };
 
-        let can_concat_occur_after_current =
-            escape_flag || (current != '(' && !RegexSymbol::is_binary_operator(current));
+        let can_concat_occur_after_current = escape_flag
+            || (current != '('
+                && !RegexSymbol::is_binary_operator(current)
+                && !RegexSymbol::is_prefix_operator(current));
         let can_concat_occur_before_next = next != ')' && !RegexSymbol::is_operator(next);
 
         if can_concat_occur_after_current && can_concat_occur_before_next {
@@ -82,7 +89,7 @@ It iterates over each character and parks them according to the following rules
     - This last step is to just clean things up and finalise the postfix notation by utilising the LIFO output
       of any remaining operators on the stack.
 */
-fn convert(formatted: Vec<RegexSymbol>) -> Result<VecDeque<RegexSymbol>, String> {
+pub(crate) fn convert(formatted: Vec<RegexSymbol>) -> Result<VecDeque<RegexSymbol>, String> {
     let mut output_queue: VecDeque<RegexSymbol> = VecDeque::new();
     let mut operator_stack: Vec<RegexSymbol> = Vec::new();
 
@@ -120,7 +127,8 @@ fn convert(formatted: Vec<RegexSymbol>) -> Result<VecDeque<RegexSymbol>, String>
                 }
             }
 
-            // Don't bother popping unary ops, they're all of the same precedence and always follows the operand.
+            // Don't bother popping unary ops, the postfix ones always follow their operand and ~ always comes
+            // before it, so there's nothing to their left they could bind to.
             operator_stack.push(symbol);
         } else {
             output_queue.push_back(symbol);
@@ -137,16 +145,50 @@ fn convert(formatted: Vec<RegexSymbol>) -> Result<VecDeque<RegexSymbol>, String>
         output_queue.push_back(operator_stack.pop().unwrap());
     }
 
+    check_operands(&output_queue)?;
+
     return Ok(output_queue);
 }
 
+// Every operator needs its operands on the stack by the time it's reached. The checks on the infix regex catch
+// operators next to each other, but () adds nothing to the output queue, so e.g. ~() and ()&a still get through to
+// here with an operand missing.
+fn check_operands(postfix_regex: &VecDeque<RegexSymbol>) -> Result<(), String> {
+    let mut num_of_operands: usize = 0;
+
+    for symbol in postfix_regex {
+        let needed = match RegexSymbol::get_type(symbol) {
+            OperatorType::None => 0,
+            OperatorType::Unary => 1,
+            OperatorType::Binary => 2,
+        };
+
+        if num_of_operands < needed {
+            return Err("Error - Empty brackets can't be used as an operand".to_string());
+        }
+
+        num_of_operands = num_of_operands - needed + 1;
+    }
+
+    return Ok(());
+}
+
 fn check_start_and_end_chars(regex: String) -> Result<String, String> {
-    if regex.starts_with(|c| RegexSymbol::is_operator(c)) {
+    if regex.starts_with(RegexSymbol::is_operator) {
         return Err("Error - Illegal operator usage at start of string".to_string());
     }
 
-    if regex.ends_with(|c| RegexSymbol::is_binary_operator(c)) {
-        return Err("Error - Illegal operator usage at end of string".to_string());
+    // The last character isn't an operator if it's escaped, i.e. there's an odd number of backslashes before it
+    let mut reversed = regex.chars().rev();
+    let last = reversed.next();
+    let is_escaped = reversed.take_while(|c| *c == '\\').count() % 2 == 1;
+
+    if let Some(last) = last {
+        if !is_escaped
+            && (RegexSymbol::is_binary_operator(last) || RegexSymbol::is_prefix_operator(last))
+        {
+            return Err("Error - Illegal operator usage at end of string".to_string());
+        }
     }
 
     return Ok(regex);
@@ -155,6 +197,7 @@ fn check_start_and_end_chars(regex: String) -> Result<String, String> {
 fn check_for_illegal_operator_sequences(regex: String) -> Result<String, String> {
     let mut i = 0;
     let mut iter = regex.chars().peekable();
+    let mut escape_flag = false;
 
     while let Some(c) = iter.next() {
         let current: char = c;
@@ -163,7 +206,16 @@ fn check_for_illegal_operator_sequences(regex: String) -> Result<String, String>
             None => continue,
         };
 
-        if RegexSymbol::is_binary_operator(current) && RegexSymbol::is_operator(next)
+        // An escaped character is never an operator, whatever comes after it
+        if escape_flag || current == '\\' {
+            escape_flag = !escape_flag;
+            i += 1;
+            continue;
+        }
+
+        // ~ needs an operand straight after it, the same as a binary operator does
+        if (RegexSymbol::is_binary_operator(current) || RegexSymbol::is_prefix_operator(current))
+            && (RegexSymbol::is_operator(next) || next == ')')
             || RegexSymbol::is_unary_operator(current) && RegexSymbol::is_unary_operator(next)
         {
             return Err(format!(
@@ -178,6 +230,7 @@ fn check_for_illegal_operator_sequences(regex: String) -> Result<String, String>
     return Ok(regex);
 }
 
+#[cfg(test)]
 mod test {
     use super::*;
 
@@ -378,14 +431,61 @@ mod test {
         }
     }
 
+    #[test]
+    fn given_examples_with_intersection_and_complement_when_transforming_it_should_correctly_output_postfix(
+    ) {
+        let examples = [
+            "a&b",
+            "ab&c",
+            "a|b&c",
+            "~ab",
+            "~a*",
+            "x~(a|b)",
+            "(a|b)+&~(ab)",
+            r"a\&\~",
+        ];
+        let answers = [
+            "ab&",
+            "ab.c&",
+            "abc&|",
+            "a~b.",
+            "a*~",
+            "xab|~.",
+            "ab|+ab.~&",
+            "a&.~.",
+        ];
+
+        for i in 0..examples.len() {
+            let result: String = transform(examples[i].to_string())
+                .unwrap()
+                .iter()
+                .map(|x| x.to_string())
+                .collect();
+
+            assert_eq!(result, answers[i], "{}", examples[i]);
+        }
+    }
+
     #[test]
     fn given_invalid_examples_when_transforming_it_should_reject_them() {
-        let examples = ["*a", "|a", "(a))", "((a)", "a|", "a||a", "a**a"];
+        let examples = [
+            "*a", "|a", "(a))", "((a)", "a|", "a||a", "a**a", "&a", "a&", "~", "a~", "~*a", "a&|b",
+            "(a~)", "(a|)", "~()", "()&a", "a|()", "()*", "a()",
+        ];
 
         for example in examples {
             let result = transform(example.to_string());
 
-            assert!(result.is_err());
+            assert!(result.is_err(), "{}", example);
+        }
+    }
+
+    #[test]
+    fn given_escaped_operators_before_a_close_bracket_when_transforming_it_should_accept_them() {
+        let examples = [r"(a\|)", r"(\~)", r"(a\&)", r"\**"];
+
+        for example in examples {
+            assert!(transform(example.to_string()).is_ok(), "{}", example);
         }
     }
 }
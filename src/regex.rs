@@ -16,11 +16,15 @@ pub enum RegexSymbol {
     Star,
     Concat,
     Alternation,
+    Intersection,
+    Complement,
     Open,
     Close,
     Char(char),
 }
 
+// Every character in the regex, in sorted order and only once each. A DFA built over an alphabet with repeats would
+// just work out the same transitions again for each one.
 pub fn get_alphabet(regex: &VecDeque<RegexSymbol>) -> Vec<char> {
     let mut alphabet: Vec<char> = Vec::new();
 
@@ -30,9 +34,27 @@ pub fn get_alphabet(regex: &VecDeque<RegexSymbol>) -> Vec<char> {
         }
     }
 
+    alphabet.sort_unstable();
+    alphabet.dedup();
+
     return alphabet;
 }
 
+// The postfix regex matching exactly the literal, without parsing it so none of its characters are operators
+pub fn literal_postfix(literal: &str) -> VecDeque<RegexSymbol> {
+    let mut postfix_regex: VecDeque<RegexSymbol> = VecDeque::new();
+
+    for (i, c) in literal.chars().enumerate() {
+        postfix_regex.push_back(RegexSymbol::Char(c));
+
+        if i > 0 {
+            postfix_regex.push_back(RegexSymbol::Concat);
+        }
+    }
+
+    return postfix_regex;
+}
+
 impl RegexSymbol {
     pub fn from_char(c: char) -> RegexSymbol {
         return match c {
@@ -40,6 +62,8 @@ impl RegexSymbol {
             '+' => RegexSymbol::Plus,
             '*' => RegexSymbol::Star,
             '|' => RegexSymbol::Alternation,
+            '&' => RegexSymbol::Intersection,
+            '~' => RegexSymbol::Complement,
             '(' => RegexSymbol::Open,
             ')' => RegexSymbol::Close,
             c => RegexSymbol::Char(c),
@@ -52,6 +76,8 @@ impl RegexSymbol {
             '+' => Ok(RegexSymbol::Char('+')),
             '*' => Ok(RegexSymbol::Char('*')),
             '|' => Ok(RegexSymbol::Char('|')),
+            '&' => Ok(RegexSymbol::Char('&')),
+            '~' => Ok(RegexSymbol::Char('~')),
             '(' => Ok(RegexSymbol::Char('(')),
             ')' => Ok(RegexSymbol::Char(')')),
             't' => Ok(RegexSymbol::Char('\t')),
@@ -66,10 +92,12 @@ impl RegexSymbol {
 
     pub fn get_precedence(&self) -> OperatorPrecedence {
         return match self {
-            RegexSymbol::Optional => 3,
-            RegexSymbol::Plus => 3,
-            RegexSymbol::Star => 3,
-            RegexSymbol::Concat => 2,
+            RegexSymbol::Optional => 5,
+            RegexSymbol::Plus => 5,
+            RegexSymbol::Star => 5,
+            RegexSymbol::Complement => 4,
+            RegexSymbol::Concat => 3,
+            RegexSymbol::Intersection => 2,
             RegexSymbol::Alternation => 1,
             _ => 0,
         };
@@ -80,8 +108,10 @@ impl RegexSymbol {
             RegexSymbol::Optional => OperatorType::Unary,
             RegexSymbol::Plus => OperatorType::Unary,
             RegexSymbol::Star => OperatorType::Unary,
+            RegexSymbol::Complement => OperatorType::Unary,
             RegexSymbol::Concat => OperatorType::Binary,
             RegexSymbol::Alternation => OperatorType::Binary,
+            RegexSymbol::Intersection => OperatorType::Binary,
             _ => OperatorType::None,
         };
     }
@@ -95,7 +125,12 @@ impl RegexSymbol {
     }
 
     pub fn is_binary_operator(c: char) -> bool {
-        return c == '|';
+        return c == '|' || c == '&';
+    }
+
+    // Unlike the other unary operators this comes before its operand, so it's left out of is_operator
+    pub fn is_prefix_operator(c: char) -> bool {
+        return c == '~';
     }
 }
 
@@ -107,6 +142,8 @@ impl fmt::Display for RegexSymbol {
             RegexSymbol::Star => write!(f, "*"),
             RegexSymbol::Concat => write!(f, "."),
             RegexSymbol::Alternation => write!(f, "|"),
+            RegexSymbol::Intersection => write!(f, "&"),
+            RegexSymbol::Complement => write!(f, "~"),
             RegexSymbol::Open => write!(f, "("),
             RegexSymbol::Close => write!(f, ")"),
             RegexSymbol::Char(c) => write!(f, "{}", c),
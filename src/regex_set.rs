@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    automata::PatternID,
+    builder::{BuildError, RegexBuilder, SizeLimits},
+    lazy_dfa::{CacheExhausted, LazyDfa, SearchKind},
+    nfa::{build_multi_nfa_with, NfaConstruction},
+    pikevm::PikeVm,
+    pool::Pool,
+    regex::RegexSymbol,
+};
+
+// Matches several patterns at once with a single automaton, and reports which of them matched.
+// Like Regex, it runs on a lazy DFA until that outgrows its cache, then switches to the Pike VM for good, and can be
+// shared between threads the same way.
+pub struct RegexSet {
+    patterns: Vec<String>,
+    dfas: Pool<LazyDfa>,
+    pike_vm: PikeVm,
+    dfa_exhausted: AtomicBool,
+}
+
+impl RegexSet {
+    pub fn new<S: AsRef<str>>(queries: &[S], ignore_case: bool) -> Result<RegexSet, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .build_set(queries);
+    }
+
+    pub fn with_cache_capacity<S: AsRef<str>>(
+        queries: &[S],
+        ignore_case: bool,
+        cache_capacity: usize,
+    ) -> Result<RegexSet, BuildError> {
+        return RegexBuilder::new()
+            .ignore_case(ignore_case)
+            .cache_capacity(cache_capacity)
+            .build_set(queries);
+    }
+
+    pub(crate) fn from_postfix(
+        patterns: Vec<String>,
+        postfix_regexes: Vec<VecDeque<RegexSymbol>>,
+        ignore_case: bool,
+        cache_capacity: usize,
+        limits: SizeLimits,
+        construction: NfaConstruction,
+    ) -> Result<RegexSet, BuildError> {
+        limits.check_nfa(&postfix_regexes)?;
+
+        let (handle, nfa) =
+            build_multi_nfa_with(postfix_regexes, ignore_case, construction, limits)?;
+        let nfa = Arc::new(nfa);
+
+        let pike_vm = PikeVm::new(&handle, nfa.clone());
+        let dfa = LazyDfa::new(handle, nfa, SearchKind::Earliest, cache_capacity)
+            .with_limits(limits.dfa_states, limits.dfa_transitions);
+
+        return Ok(RegexSet {
+            patterns,
+            dfas: Pool::new(dfa),
+            pike_vm,
+            dfa_exhausted: AtomicBool::new(false),
+        });
+    }
+
+    // Returns the index of every pattern with a match in the haystack, in increasing order
+    pub fn matches(&self, haystack: &str) -> Vec<PatternID> {
+        if !self.dfa_exhausted.load(Ordering::Relaxed) {
+            match self.dfas.get().try_matching_patterns(haystack) {
+                Ok(found) => return found.into_iter().collect(),
+                Err(CacheExhausted) => self.dfa_exhausted.store(true, Ordering::Relaxed),
+            }
+        }
+
+        return self
+            .pike_vm
+            .matching_patterns(haystack)
+            .into_iter()
+            .collect();
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        if !self.dfa_exhausted.load(Ordering::Relaxed) {
+            match self.dfas.get().try_is_match(haystack) {
+                Ok(result) => return result,
+                Err(CacheExhausted) => self.dfa_exhausted.store(true, Ordering::Relaxed),
+            }
+        }
+
+        return self.pike_vm.is_match(haystack);
+    }
+
+    pub fn get_pattern(&self, index: PatternID) -> Option<&str> {
+        return self.patterns.get(index).map(|pattern| pattern.as_str());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.patterns.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.patterns.is_empty();
+    }
+
+    pub fn is_using_dfa(&self) -> bool {
+        return !self.dfa_exhausted.load(Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn given_several_patterns_when_matching_should_report_every_pattern_that_matched() {
+        let set = RegexSet::new(&["you", "us", "(a|e)+r", "frog"], false).unwrap();
+
+        let examples = [
+            ("I'm nobody! Who are you?", vec![0, 2]),
+            ("They'd banish us, you know.", vec![0, 1]),
+            ("How public, like a frog", vec![3]),
+            ("To an admiring bog!", vec![]),
+        ];
+
+        for (haystack, answer) in examples {
+            assert_eq!(set.matches(haystack), answer, "{}", haystack);
+            assert_eq!(set.is_match(haystack), !answer.is_empty());
+        }
+
+        assert!(set.is_using_dfa());
+    }
+
+    #[test]
+    fn given_patterns_matching_the_same_text_when_matching_should_report_all_of_them() {
+        let set = RegexSet::new(&["ab", "a(b|c)", "b?", "x"], false).unwrap();
+
+        assert_eq!(set.matches("ab"), vec![0, 1, 2]);
+        assert_eq!(set.matches(""), vec![2]);
+    }
+
+    #[test]
+    fn given_ignore_case_when_matching_should_fold_every_pattern() {
+        let set = RegexSet::new(&["rust", "σοφια"], true).unwrap();
+
+        assert_eq!(set.matches("TRUST ΣΟΦΙΑ"), vec![0, 1]);
+    }
+
+    #[test]
+    fn given_dfa_that_exceeds_the_cache_when_matching_should_fall_back_to_the_pike_vm() {
+        let exponential = format!("(a|b)*a{}c", "(a|b)".repeat(10));
+        let set =
+            RegexSet::with_cache_capacity(&[exponential.as_str(), "bbbb"], false, 1024).unwrap();
+
+        let haystack: String = (0..1024)
+            .map(|i: u32| format!("{:010b}", i).replace('0', "a").replace('1', "b"))
+            .collect();
+
+        assert_eq!(set.matches(&haystack), vec![1]);
+        assert!(!set.is_using_dfa());
+
+        assert_eq!(set.matches(&format!("{}c", haystack)), vec![0, 1]);
+    }
+
+    #[test]
+    fn given_no_patterns_when_matching_should_never_match() {
+        let set = RegexSet::new::<&str>(&[], false).unwrap();
+
+        assert!(set.is_empty());
+        assert!(set.matches("anything").is_empty());
+    }
+
+    #[test]
+    fn given_set_when_shared_between_threads_should_report_the_same_patterns() {
+        let set = RegexSet::new(&["you", "us", "frog"], false).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    assert_eq!(set.matches("are you one of us?"), vec![0, 1]);
+                    assert!(set.matches("a toad").is_empty());
+                });
+            }
+        });
+    }
+}
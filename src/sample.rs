@@ -0,0 +1,353 @@
+/* Generates strings a pattern matches, by walking its DFA. There are two ways of going about it:
+ *
+ * ShortestMatches      every matched string in length-lexicographic order, i.e. all the shortest ones in
+ *                      alphabetical order, then all the ones a character longer, and so on
+ * random_match         a random matched string of at most some length, with every one of them equally likely
+ *
+ * Both only follow transitions to live states (ones that can still reach an accepting state), so they never waste
+ * time on strings that can't be finished into a match. Characters outside the pattern's alphabet all behave the
+ * same way, so they're all stood in for by one readable character, e.g. ~(a*) gives "b" rather than every
+ * character there is.
+ *
+ * Being whole string matches, these are just what's needed for test fixtures, e.g. sampling
+ * "ERROR (disk|net) (0|1)+" gives lines a log parser has to accept.
+ */
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::complete_dfa::CompleteDfa;
+
+// Strings are enumerated one length at a time with a depth first search, which only ever holds the string it's
+// building rather than every string of the current length, as there can be exponentially many of those, e.g.
+// (a|b|c|d|e|f|g|h|i|j)*0123456x has 10^8 before the first match with a letter in it
+pub struct ShortestMatches<'a> {
+    dfa: &'a CompleteDfa,
+    chars: Vec<(char, usize)>,
+    live: Vec<bool>,
+    // finishes[n][state] is whether some string of exactly n characters leads from the state to a match, so the
+    // search never goes down a branch that has no match of the current length at the end of it
+    finishes: Vec<Vec<bool>>,
+    // The live states some string of the current length leads to. Once there are none, there are no longer
+    // matches either
+    reachable: Vec<bool>,
+    length: usize,
+    // The states along the string being built, each with the index of the next character to try from it
+    stack: Vec<(usize, usize)>,
+    string: String,
+    started: bool,
+}
+
+impl<'a> ShortestMatches<'a> {
+    pub fn new(dfa: &'a CompleteDfa) -> ShortestMatches<'a> {
+        let live = dfa.live_states();
+        let mut reachable = vec![false; dfa.num_of_states()];
+
+        reachable[dfa.get_start()] = live[dfa.get_start()];
+
+        let finishes = vec![(0..dfa.num_of_states())
+            .map(|state| dfa.is_accepting(state))
+            .collect()];
+
+        return ShortestMatches {
+            dfa,
+            chars: dfa.example_chars(),
+            live,
+            finishes,
+            reachable,
+            length: 0,
+            stack: Vec::new(),
+            string: String::new(),
+            started: false,
+        };
+    }
+
+    // Fills in finishes up to strings of the given length
+    fn count_up_to(&mut self, length: usize) {
+        while self.finishes.len() <= length {
+            let previous = &self.finishes[self.finishes.len() - 1];
+            let row = (0..self.dfa.num_of_states())
+                .map(|state| {
+                    self.chars
+                        .iter()
+                        .any(|(_, column)| previous[self.dfa.next_state(state, *column)])
+                })
+                .collect();
+
+            self.finishes.push(row);
+        }
+    }
+
+    // Moves on to strings a character longer
+    fn lengthen(&mut self) {
+        let mut reachable = vec![false; self.dfa.num_of_states()];
+
+        for state in (0..self.dfa.num_of_states()).filter(|state| self.reachable[*state]) {
+            for (_, column) in self.chars.iter() {
+                let next = self.dfa.next_state(state, *column);
+
+                reachable[next] = self.live[next];
+            }
+        }
+
+        self.reachable = reachable;
+        self.length += 1;
+        self.started = false;
+    }
+
+    fn backtrack(&mut self) {
+        self.stack.pop();
+
+        // The start state has no character leading to it
+        if !self.stack.is_empty() {
+            self.string.pop();
+        }
+    }
+}
+
+impl<'a> Iterator for ShortestMatches<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if !self.started {
+                if !self.reachable.iter().any(|reachable| *reachable) {
+                    return None;
+                }
+
+                self.count_up_to(self.length);
+
+                if self.finishes[self.length][self.dfa.get_start()] {
+                    self.stack.push((self.dfa.get_start(), 0));
+                }
+
+                self.started = true;
+            }
+
+            // Trying the characters in order from each state keeps the strings of each length in alphabetical order
+            while let Some(&(state, i)) = self.stack.last() {
+                let remaining = self.length + 1 - self.stack.len();
+
+                if remaining == 0 {
+                    let found = self.string.clone();
+                    self.backtrack();
+
+                    return Some(found);
+                }
+
+                if i == self.chars.len() {
+                    self.backtrack();
+                    continue;
+                }
+
+                self.stack.last_mut().unwrap().1 += 1;
+
+                let (c, column) = self.chars[i];
+                let next = self.dfa.next_state(state, column);
+
+                if self.finishes[remaining - 1][next] {
+                    self.string.push(c);
+                    self.stack.push((next, 0));
+                }
+            }
+
+            self.lengthen();
+        }
+    }
+}
+
+// A xorshift generator, which is plenty random enough for picking examples
+// Link: https://en.wikipedia.org/wiki/Xorshift
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Xorshift gets stuck on 0 forever
+        return Rng { state: seed.max(1) };
+    }
+
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        return Rng::new(nanos);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        return self.state;
+    }
+
+    // Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+}
+
+// A random string the DFA matches with at most max_len characters, or None if it matches none that short.
+//
+// counts[n][state] is how many strings of length n lead from the state to a match, so picking the length and then
+// each character in proportion to how many matches lie that way makes every match equally likely. The counts
+// are floats since they grow exponentially with the length.
+pub fn random_match(dfa: &CompleteDfa, max_len: usize, rng: &mut Rng) -> Option<String> {
+    let chars = dfa.example_chars();
+    let mut counts: Vec<Vec<f64>> = Vec::with_capacity(max_len + 1);
+
+    counts.push(
+        (0..dfa.num_of_states())
+            .map(|state| if dfa.is_accepting(state) { 1.0 } else { 0.0 })
+            .collect(),
+    );
+
+    for n in 1..=max_len {
+        let row = (0..dfa.num_of_states())
+            .map(|state| {
+                chars
+                    .iter()
+                    .map(|(_, column)| counts[n - 1][dfa.next_state(state, *column)])
+                    .sum()
+            })
+            .collect();
+
+        counts.push(row);
+    }
+
+    let start = dfa.get_start();
+    let lengths: Vec<f64> = counts.iter().map(|row| row[start]).collect();
+    let mut remaining = pick(&lengths, rng)?;
+
+    let mut string = String::new();
+    let mut state = start;
+
+    while remaining > 0 {
+        let weights: Vec<f64> = chars
+            .iter()
+            .map(|(_, column)| counts[remaining - 1][dfa.next_state(state, *column)])
+            .collect();
+
+        let (c, column) = chars[pick(&weights, rng)?];
+
+        string.push(c);
+        state = dfa.next_state(state, column);
+        remaining -= 1;
+    }
+
+    return Some(string);
+}
+
+// Picks an index with probability in proportion to its weight, or None if they're all 0
+fn pick(weights: &[f64], rng: &mut Rng) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut target = rng.next_f64() * total;
+
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return Some(i);
+        }
+
+        target -= weight;
+    }
+
+    // Rounding can leave a little over at the end, which belongs to the last index that could be picked
+    return weights.iter().rposition(|weight| *weight > 0.0);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shortest(query: &str, count: usize) -> Vec<String> {
+        let dfa = CompleteDfa::new(query, false).unwrap();
+
+        return ShortestMatches::new(&dfa).take(count).collect();
+    }
+
+    #[test]
+    fn given_pattern_when_enumerating_should_give_the_shortest_matches_in_length_lexicographic_order(
+    ) {
+        assert_eq!(
+            shortest("(b|a)*c", 7),
+            vec!["c", "ac", "bc", "aac", "abc", "bac", "bbc"]
+        );
+        assert_eq!(shortest("dog|cat|ox", 10), vec!["ox", "cat", "dog"]);
+        assert_eq!(shortest("a?", 10), vec!["", "a"]);
+        assert_eq!(shortest("(aaa)*", 3), vec!["", "aaa", "aaaaaa"]);
+    }
+
+    #[test]
+    fn given_many_prefixes_before_the_first_match_when_enumerating_should_not_hold_them_all() {
+        assert_eq!(
+            shortest("(a|b|c|d|e|f|g|h|i|j)*0123456x", 3),
+            vec!["0123456x", "a0123456x", "b0123456x"]
+        );
+    }
+
+    #[test]
+    fn given_pattern_matching_nothing_when_enumerating_should_end_straight_away() {
+        assert!(shortest("a&b", 5).is_empty());
+        assert!(shortest("~(a*)&a*", 5).is_empty());
+    }
+
+    #[test]
+    fn given_complement_when_enumerating_should_use_a_readable_character_for_everything_else() {
+        assert_eq!(shortest("~(a*)", 3), vec!["b", "ab", "ba"]);
+    }
+
+    #[test]
+    fn given_length_bound_when_drawing_random_matches_should_only_give_matches_within_it() {
+        let dfa = CompleteDfa::new("x(a|b)*y", false).unwrap();
+        let mut rng = Rng::new(42);
+
+        for _ in 0..100 {
+            let string = random_match(&dfa, 6, &mut rng).unwrap();
+
+            assert!(dfa.is_match(&string), "{}", string);
+            assert!(string.chars().count() <= 6, "{}", string);
+        }
+
+        assert_eq!(random_match(&dfa, 1, &mut rng), None);
+    }
+
+    #[test]
+    fn given_same_seed_when_drawing_random_matches_should_give_the_same_strings() {
+        let dfa = CompleteDfa::new("(a|b|c)+", false).unwrap();
+        let draw = |seed| {
+            let mut rng = Rng::new(seed);
+
+            return (0..5)
+                .map(|_| random_match(&dfa, 8, &mut rng).unwrap())
+                .collect::<Vec<String>>();
+        };
+
+        assert_eq!(draw(7), draw(7));
+    }
+
+    #[test]
+    fn given_finite_language_when_drawing_random_matches_should_give_each_about_as_often() {
+        let dfa = CompleteDfa::new("a|bb|cc", false).unwrap();
+        let mut rng = Rng::new(1);
+        let mut seen = [0; 3];
+
+        for _ in 0..3000 {
+            match random_match(&dfa, 2, &mut rng).unwrap().as_str() {
+                "a" => seen[0] += 1,
+                "bb" => seen[1] += 1,
+                "cc" => seen[2] += 1,
+                other => panic!("unexpected match {}", other),
+            }
+        }
+
+        assert!(seen.iter().all(|n| *n > 800), "{:?}", seen);
+    }
+}
@@ -0,0 +1,609 @@
+/* Turns a DFA back into a regular expression by state elimination. The DFA gets a new start state with an empty
+ * edge to the old one and a new accept state with an empty edge from each accepting state, and every edge is
+ * labelled with a regex rather than a character. Then one by one each of the old states is removed, and for every
+ * pair of edges i -> q and q -> j through the state q being removed, the edge i -> j gets the alternative
+ *
+ *      (i -> q)(q -> q)*(q -> j)
+ *
+ * so the same strings still lead from i to j without going through q. Once only the new start and accept states
+ * are left, the label on the edge between them matches exactly what the DFA did.
+ *
+ * Left as it is the result grows fast and is full of things like (a|b)(a|b)*, so the regexes get simplified as
+ * they're built up: empty strings and empty sets cancel out, alternatives are deduplicated and have their common
+ * prefixes and suffixes factored out, xx* becomes x+, and so on.
+ *
+ * Link: https://en.wikipedia.org/wiki/Kleene%27s_algorithm
+ */
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use crate::{automata::AutomataState, graph::Graph};
+
+type NodeIndex = usize;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) enum Expr {
+    // Matches nothing at all
+    Empty,
+    // Matches just the empty string
+    Epsilon,
+    Char(char),
+    // Any one character that isn't in the set, so NotIn of the empty set is any character at all
+    NotIn(Rc<BTreeSet<char>>),
+    Concat(Vec<Expr>),
+    // Always has at least two alternatives, none of which match the empty string on their own
+    Alternation(Vec<Expr>),
+    Optional(Box<Expr>),
+    Star(Box<Expr>),
+    Plus(Box<Expr>),
+    Complement(Box<Expr>),
+}
+
+// Turns the DFA dfa::build_dfa gives into a regex matching the same strings, e.g. for the DFA of (a|b)*abb it
+// gives (a|b)*abb back. Every edge is taken to be for just its own character.
+pub fn dfa_to_regex(start: NodeIndex, dfa: &Graph<AutomataState, char>) -> String {
+    let mut accepting: Vec<bool> = Vec::new();
+    let mut edges: Vec<(NodeIndex, NodeIndex, Expr)> = Vec::new();
+
+    for node in 0..dfa.num_of_nodes() {
+        accepting.push(dfa.get_node_data(&node).unwrap().is_accepting());
+
+        for edge in dfa.outgoing_edges(node).unwrap() {
+            let c = *dfa.get_edge_data(&edge).unwrap();
+
+            edges.push((node, dfa.traverse(edge).unwrap(), Expr::Char(c)));
+        }
+    }
+
+    return eliminate(start, &accepting, edges).to_string();
+}
+
+// The regex for the strings leading from the start state to an accepting one, given the edges between states
+pub(crate) fn eliminate(
+    start: NodeIndex,
+    accepting: &[bool],
+    edges: Vec<(NodeIndex, NodeIndex, Expr)>,
+) -> Expr {
+    let num_of_states = accepting.len();
+    let new_start = num_of_states;
+    let new_accept = num_of_states + 1;
+
+    let mut labels: BTreeMap<(NodeIndex, NodeIndex), Expr> = BTreeMap::new();
+
+    for (from, to, expr) in edges {
+        add_label(&mut labels, from, to, expr);
+    }
+
+    add_label(&mut labels, new_start, start, Expr::Epsilon);
+
+    for state in (0..num_of_states).filter(|s| accepting[*s]) {
+        add_label(&mut labels, state, new_accept, Expr::Epsilon);
+    }
+
+    // States that aren't on any path from the start to an accepting state can't add anything to the regex
+    let useful = useful_states(num_of_states + 2, new_start, new_accept, &labels);
+    labels.retain(|(from, to), _| useful[*from] && useful[*to]);
+
+    let mut remaining: BTreeSet<NodeIndex> = (0..num_of_states).filter(|s| useful[*s]).collect();
+
+    while !remaining.is_empty() {
+        // Removing the state with the fewest paths through it first keeps the regexes on the edges small
+        let state = *remaining
+            .iter()
+            .min_by_key(|state| {
+                let ins = labels.keys().filter(|(f, t)| t == *state && f != t).count();
+                let outs = labels.keys().filter(|(f, t)| f == *state && f != t).count();
+
+                return ins * outs;
+            })
+            .unwrap();
+
+        remaining.remove(&state);
+
+        let repeat = match labels.remove(&(state, state)) {
+            Some(label) => Expr::star(label),
+            None => Expr::Epsilon,
+        };
+
+        let ins: Vec<(NodeIndex, Expr)> = take_edges(&mut labels, |(_, to)| *to == state)
+            .map(|((from, _), label)| (from, label))
+            .collect();
+        let outs: Vec<(NodeIndex, Expr)> = take_edges(&mut labels, |(from, _)| *from == state)
+            .map(|((_, to), label)| (to, label))
+            .collect();
+
+        for (from, into) in ins.iter() {
+            for (to, out_of) in outs.iter() {
+                let through =
+                    Expr::concat(Expr::concat(into.clone(), repeat.clone()), out_of.clone());
+
+                add_label(&mut labels, *from, *to, through);
+            }
+        }
+    }
+
+    return labels
+        .remove(&(new_start, new_accept))
+        .unwrap_or(Expr::Empty);
+}
+
+// Edges between the same states get joined up into one alternation
+fn add_label(
+    labels: &mut BTreeMap<(NodeIndex, NodeIndex), Expr>,
+    from: NodeIndex,
+    to: NodeIndex,
+    expr: Expr,
+) {
+    let label = match labels.remove(&(from, to)) {
+        Some(label) => Expr::alternate(label, expr),
+        None => expr,
+    };
+
+    labels.insert((from, to), label);
+}
+
+fn take_edges(
+    labels: &mut BTreeMap<(NodeIndex, NodeIndex), Expr>,
+    filter: impl Fn(&(NodeIndex, NodeIndex)) -> bool,
+) -> impl Iterator<Item = ((NodeIndex, NodeIndex), Expr)> {
+    let keys: Vec<(NodeIndex, NodeIndex)> = labels.keys().filter(|k| filter(k)).copied().collect();
+    let taken: Vec<((NodeIndex, NodeIndex), Expr)> = keys
+        .into_iter()
+        .map(|key| (key, labels.remove(&key).unwrap()))
+        .collect();
+
+    return taken.into_iter();
+}
+
+// The states reachable from the start that can also reach the accept state
+fn useful_states(
+    num_of_states: usize,
+    start: NodeIndex,
+    accept: NodeIndex,
+    labels: &BTreeMap<(NodeIndex, NodeIndex), Expr>,
+) -> Vec<bool> {
+    let search = |from: NodeIndex, forwards: bool| -> Vec<bool> {
+        let mut seen = vec![false; num_of_states];
+        let mut stack: Vec<NodeIndex> = vec![from];
+
+        seen[from] = true;
+
+        while let Some(state) = stack.pop() {
+            for (f, t) in labels.keys() {
+                let (near, far) = if forwards { (*f, *t) } else { (*t, *f) };
+
+                if near == state && !seen[far] {
+                    seen[far] = true;
+                    stack.push(far);
+                }
+            }
+        }
+
+        return seen;
+    };
+
+    let reachable = search(start, true);
+    let live = search(accept, false);
+
+    return (0..num_of_states)
+        .map(|state| reachable[state] && live[state])
+        .collect();
+}
+
+impl Expr {
+    pub(crate) fn is_nullable(&self) -> bool {
+        return match self {
+            Expr::Empty | Expr::Char(_) | Expr::NotIn(_) => false,
+            Expr::Epsilon | Expr::Optional(_) | Expr::Star(_) => true,
+            Expr::Concat(items) => items.iter().all(|item| item.is_nullable()),
+            Expr::Alternation(items) => items.iter().any(|item| item.is_nullable()),
+            Expr::Plus(inner) => inner.is_nullable(),
+            Expr::Complement(inner) => !inner.is_nullable(),
+        };
+    }
+
+    fn into_items(self) -> Vec<Expr> {
+        return match self {
+            Expr::Concat(items) => items,
+            Expr::Epsilon => Vec::new(),
+            expr => vec![expr],
+        };
+    }
+
+    fn from_items(mut items: Vec<Expr>) -> Expr {
+        return match items.len() {
+            0 => Expr::Epsilon,
+            1 => items.pop().unwrap(),
+            _ => Expr::Concat(items),
+        };
+    }
+
+    pub(crate) fn concat(left: Expr, right: Expr) -> Expr {
+        if left == Expr::Empty || right == Expr::Empty {
+            return Expr::Empty;
+        }
+
+        let mut items = left.into_items();
+
+        for item in right.into_items() {
+            push_concat_item(&mut items, item);
+        }
+
+        return Expr::from_items(items);
+    }
+
+    pub(crate) fn alternate(left: Expr, right: Expr) -> Expr {
+        let mut nullable = false;
+        let mut chars: BTreeSet<char> = BTreeSet::new();
+        let mut not_in: Option<BTreeSet<char>> = None;
+        let mut others: BTreeSet<Expr> = BTreeSet::new();
+
+        let mut pending = vec![left, right];
+
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expr::Empty => {}
+                Expr::Epsilon => nullable = true,
+                Expr::Optional(inner) => {
+                    nullable = true;
+                    pending.push(*inner);
+                }
+                Expr::Alternation(items) => pending.extend(items),
+                Expr::Char(c) => {
+                    chars.insert(c);
+                }
+                // Any character outside either set is outside their intersection
+                Expr::NotIn(excluded) => {
+                    not_in = Some(match not_in {
+                        Some(set) => set.intersection(&excluded).copied().collect(),
+                        None => (*excluded).clone(),
+                    });
+                }
+                expr => {
+                    nullable |= expr.is_nullable();
+                    others.insert(expr);
+                }
+            }
+        }
+
+        // x* already matches x and x+, and x+ already matches x
+        let repeated = |expr: &Expr| {
+            let starred = Expr::Star(Box::new(expr.clone()));
+            let plussed = Expr::Plus(Box::new(expr.clone()));
+
+            return others.contains(&starred) || others.contains(&plussed);
+        };
+
+        chars.retain(|c| !repeated(&Expr::Char(*c)));
+
+        let mut items: Vec<Expr> = Vec::new();
+
+        // The characters can only have anything in common with the other alternatives if they're still separate
+        let mut rest: Vec<Expr> = match not_in {
+            Some(excluded) => {
+                let excluded: BTreeSet<char> = excluded.difference(&chars).copied().collect();
+                items.push(Expr::NotIn(Rc::new(excluded)));
+
+                Vec::new()
+            }
+            None => chars.into_iter().map(Expr::Char).collect(),
+        };
+
+        rest.extend(
+            others
+                .iter()
+                .filter(|expr| !repeated(expr))
+                .filter(|expr| match expr {
+                    Expr::Plus(inner) => !others.contains(&Expr::Star(inner.clone())),
+                    _ => true,
+                })
+                .cloned(),
+        );
+
+        items.extend(factor(rest));
+
+        let alternation = match items.len() {
+            0 if nullable => return Expr::Epsilon,
+            0 => return Expr::Empty,
+            1 => items.pop().unwrap(),
+            _ => Expr::Alternation(items),
+        };
+
+        if nullable {
+            return Expr::optional(alternation);
+        }
+
+        return alternation;
+    }
+
+    pub(crate) fn optional(expr: Expr) -> Expr {
+        return match expr {
+            Expr::Empty | Expr::Epsilon => Expr::Epsilon,
+            Expr::Plus(inner) => Expr::Star(inner),
+            expr if expr.is_nullable() => expr,
+            expr => Expr::Optional(Box::new(expr)),
+        };
+    }
+
+    pub(crate) fn star(expr: Expr) -> Expr {
+        return match expr {
+            Expr::Empty | Expr::Epsilon => Expr::Epsilon,
+            Expr::Star(inner) | Expr::Plus(inner) | Expr::Optional(inner) => Expr::star(*inner),
+            expr => Expr::Star(Box::new(expr)),
+        };
+    }
+
+    fn precedence(&self) -> u8 {
+        return match self {
+            Expr::Alternation(_) => 1,
+            Expr::Concat(_) => 3,
+            Expr::Complement(_) => 4,
+            Expr::Optional(_) | Expr::Star(_) | Expr::Plus(_) => 5,
+            _ => 6,
+        };
+    }
+
+    fn render(&self, precedence: u8) -> String {
+        let rendered = match self {
+            // Nothing in the syntax means nothing, but the complement of everything does
+            Expr::Empty => "~(a|~a)".to_string(),
+            Expr::Epsilon => String::new(),
+            Expr::Char(c) => escape(*c),
+            Expr::NotIn(excluded) => return render_not_in(excluded, precedence),
+            // Every string there is, i.e. a or anything but a
+            Expr::Star(inner) if matches!(&**inner, Expr::NotIn(e) if e.is_empty()) => {
+                return Expr::Alternation(vec![
+                    Expr::Char('a'),
+                    Expr::Complement(Box::new(Expr::Char('a'))),
+                ])
+                .render(precedence);
+            }
+            Expr::Concat(items) => items.iter().map(|item| item.render(4)).collect(),
+            Expr::Alternation(items) => items
+                .iter()
+                .map(|item| item.render(2))
+                .collect::<Vec<String>>()
+                .join("|"),
+            Expr::Optional(inner) => format!("{}?", inner.render(6)),
+            Expr::Star(inner) => format!("{}*", inner.render(6)),
+            Expr::Plus(inner) => format!("{}+", inner.render(6)),
+            // Anything but the empty string, as the empty string can only be written as a? and not a
+            Expr::Complement(inner) if **inner == Expr::Epsilon => "~(a?&~a)".to_string(),
+            // ~ takes the single atom after it along with any postfix operators, so anything bigger needs brackets
+            Expr::Complement(inner) => format!("~{}", inner.render(6)),
+        };
+
+        if self.precedence() < precedence {
+            return format!("({})", rendered);
+        }
+
+        return rendered;
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}", self.render(0));
+    }
+}
+
+// Appends to the items of a concatenation, turning x x* and x* x into x+ along the way
+fn push_concat_item(items: &mut Vec<Expr>, item: Expr) {
+    if let Expr::Star(inner) = &item {
+        let repeated = (**inner).clone().into_items();
+
+        if items.ends_with(&repeated) {
+            items.truncate(items.len() - repeated.len());
+            items.push(Expr::Plus(inner.clone()));
+            return;
+        }
+
+        // x+ x* and x* x* are just x+ and x*
+        match items.last() {
+            Some(Expr::Plus(last)) | Some(Expr::Star(last)) if last == inner => return,
+            _ => {}
+        }
+    }
+
+    match (items.last(), &item) {
+        (Some(Expr::Star(last)), item) if **last == *item => {
+            let last = items.pop().unwrap();
+
+            if let Expr::Star(inner) = last {
+                items.push(Expr::Plus(inner));
+            }
+        }
+        (Some(Expr::Star(last)), Expr::Plus(inner)) if last == inner => {
+            items.pop();
+            items.push(item);
+        }
+        _ => items.push(item),
+    }
+}
+
+// Pulls out the prefix or suffix shared by alternatives, e.g. abc|abd|e is ab(c|d)|e and xz|yz is (x|y)z
+fn factor(alternatives: Vec<Expr>) -> Vec<Expr> {
+    let alternatives = factor_by(alternatives, |items| items.first().cloned(), true);
+
+    return factor_by(alternatives, |items| items.last().cloned(), false);
+}
+
+fn factor_by(
+    alternatives: Vec<Expr>,
+    key: impl Fn(&[Expr]) -> Option<Expr>,
+    prefix: bool,
+) -> Vec<Expr> {
+    let mut groups: BTreeMap<Option<Expr>, Vec<Vec<Expr>>> = BTreeMap::new();
+
+    for alternative in alternatives {
+        let items = alternative.into_items();
+
+        groups.entry(key(&items)).or_default().push(items);
+    }
+
+    let mut factored: Vec<Expr> = Vec::new();
+
+    for (shared, group) in groups {
+        let Some(shared) = shared.filter(|_| group.len() > 1) else {
+            factored.extend(group.into_iter().map(Expr::from_items));
+            continue;
+        };
+
+        let rests = group
+            .into_iter()
+            .map(|mut items| {
+                if prefix {
+                    items.remove(0);
+                } else {
+                    items.pop();
+                }
+
+                return Expr::from_items(items);
+            })
+            .reduce(Expr::alternate)
+            .unwrap();
+
+        factored.push(match prefix {
+            true => Expr::concat(shared, rests),
+            false => Expr::concat(rests, shared),
+        });
+    }
+
+    return factored;
+}
+
+// Characters that are operators or can only be written as escapes get escaped, everything else is itself
+fn escape(c: char) -> String {
+    return match c {
+        c if "?+*|&~()\\".contains(c) => format!("\\{}", c),
+        '\t' => "\\t".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\u{0008}' => "\\b".to_string(),
+        c => c.to_string(),
+    };
+}
+
+// The syntax has nothing for any one character, so it's built from complements. With x any character:
+//
+//      x|~(x?)             any non empty string, call it X
+//      ~(x?|XX)            any one character apart from x
+//
+// and the characters left out of ~(...) can be anything else matching just one character, like a|b|c.
+fn render_not_in(excluded: &BTreeSet<char>, precedence: u8) -> String {
+    let x = excluded.first().copied().unwrap_or('a');
+
+    let non_empty = Expr::Alternation(vec![
+        Expr::Char(x),
+        Expr::Complement(Box::new(Expr::Optional(Box::new(Expr::Char(x))))),
+    ]);
+
+    let mut left_out: Vec<Expr> = vec![Expr::Optional(Box::new(Expr::Char(x)))];
+
+    left_out.extend(excluded.iter().filter(|c| **c != x).map(|c| Expr::Char(*c)));
+    left_out.push(Expr::Concat(vec![non_empty.clone(), non_empty]));
+
+    let one_char = Expr::Complement(Box::new(Expr::Alternation(left_out)));
+
+    // Nothing was meant to be left out, so x has to go back in
+    if excluded.is_empty() {
+        return Expr::Alternation(vec![Expr::Char(x), one_char]).render(precedence);
+    }
+
+    return one_char.render(precedence);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::SizeLimits,
+        complete_dfa::CompleteDfa,
+        dfa::build_dfa,
+        nfa::{build_nfa, get_nfa_alphabet},
+        postfixer,
+    };
+
+    fn round_trip(query: &str) -> String {
+        let postfix_regex = postfixer::transform(query.to_string()).unwrap();
+        let (handle, nfa) = build_nfa(postfix_regex, false, SizeLimits::unlimited()).unwrap();
+        let alphabet = get_nfa_alphabet(&nfa);
+        let (start, dfa) = build_dfa(handle, &nfa, alphabet);
+
+        return dfa_to_regex(start, &dfa);
+    }
+
+    #[test]
+    fn given_dfas_when_eliminating_states_should_give_an_equivalent_regex() {
+        let queries = [
+            "a",
+            "ab|ac",
+            "(a|b)*abb",
+            "a*b*",
+            "(ab)+",
+            "x(0|1)*y?",
+            "colou?r",
+            "\\(\\*\\)|\\|",
+        ];
+
+        for query in queries {
+            let regex = round_trip(query);
+
+            let original = CompleteDfa::new(query, false).unwrap();
+            let converted = CompleteDfa::new(&regex, false).unwrap();
+
+            assert_eq!(
+                original.find_difference(&converted),
+                None,
+                "{} -> {}",
+                query,
+                regex
+            );
+        }
+    }
+
+    #[test]
+    fn given_simple_dfas_when_eliminating_states_should_simplify_the_result() {
+        assert_eq!(round_trip("abc"), "abc");
+        assert_eq!(round_trip("ab|ac"), "a(b|c)");
+        assert_eq!(round_trip("a(a)*"), "a+");
+        assert_eq!(round_trip("colou?r"), "colou?r");
+    }
+
+    #[test]
+    fn given_exprs_when_simplifying_should_cancel_out_the_empty_string_and_set() {
+        let a = || Expr::Char('a');
+
+        assert_eq!(Expr::concat(Expr::Epsilon, a()), a());
+        assert_eq!(Expr::concat(Expr::Empty, a()), Expr::Empty);
+        assert_eq!(Expr::alternate(Expr::Empty, a()), a());
+        assert_eq!(Expr::alternate(Expr::Epsilon, a()).to_string(), "a?");
+        assert_eq!(Expr::star(Expr::Epsilon), Expr::Epsilon);
+        assert_eq!(Expr::star(Expr::Plus(Box::new(a()))).to_string(), "a*");
+        assert_eq!(Expr::concat(a(), Expr::star(a())).to_string(), "a+");
+    }
+
+    #[test]
+    fn given_characters_outside_sets_when_rendering_should_only_match_one_such_character() {
+        let not_ab = Expr::NotIn(Rc::new(BTreeSet::from(['a', 'b'])));
+        let any = Expr::NotIn(Rc::new(BTreeSet::new()));
+
+        for (expr, matched, unmatched) in [
+            (not_ab, vec!["c", "é"], vec!["", "a", "b", "cc"]),
+            (any, vec!["a", "z"], vec!["", "ab"]),
+        ] {
+            let dfa = CompleteDfa::new(&expr.to_string(), false).unwrap();
+
+            for input in matched {
+                assert!(dfa.is_match(input), "{} {}", expr, input);
+            }
+
+            for input in unmatched {
+                assert!(!dfa.is_match(input), "{} {}", expr, input);
+            }
+        }
+    }
+}
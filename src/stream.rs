@@ -0,0 +1,349 @@
+/* Searches input that's too big to read in all at once, or that's still arriving, e.g. a pipe. The input is read a
+ * chunk at a time into one fixed size buffer, and each line is fed through the lazy DFA as its pieces turn up, with
+ * the DFA state carried over from one chunk to the next. So a line that has a match gets written out as soon as the
+ * match is found, and the rest of it goes straight from the buffer to the output.
+ *
+ * The part of a line read before a match has been found still has to be kept, since it's needed if the line does
+ * turn out to match. So the memory used is the chunk size plus the longest stretch of a line without a match in it,
+ * rather than the whole input. The engines that can't carry on from where they left off (the literal searches,
+ * and the Pike VM once the DFA has given up) get each line whole at the end of it instead, as do patterns with a
+ * prefilter, so lines missing the literals they need get thrown away without running the DFA over them.
+ *
+ * Lines are split the same way as str::lines, so a \r right before a \n isn't part of the line. A char can also be
+ * split across two chunks, in which case its first bytes are kept back until the rest of it is read.
+ */
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::{dense_dfa::DenseDfa, matcher::LineSearch, Regex};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+// Long enough for any char to fit
+const MIN_CHUNK_SIZE: usize = 4;
+
+pub struct StreamSearcher<'r> {
+    matcher: Matcher<'r>,
+    chunk_size: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Matcher<'r> {
+    Regex(&'r Regex),
+    DenseDfa(&'r DenseDfa),
+}
+
+// Where the search is up to in the current line
+enum LineState<'r> {
+    Regex(LineSearch<'r>),
+    DenseDfa {
+        dfa: &'r DenseDfa,
+        state: usize,
+        matched: bool,
+    },
+}
+
+struct Line<'r, W: Write> {
+    matcher: Matcher<'r>,
+    state: LineState<'r>,
+    writer: W,
+    // The line so far, until it's known to match, after which it's been written out
+    buffer: String,
+    matched: bool,
+    // Whether anything of the line has been read yet, since the input might not end with a newline
+    started: bool,
+    pending_cr: bool,
+    num_of_matches: usize,
+}
+
+impl<'r> StreamSearcher<'r> {
+    pub fn new(regex: &'r Regex) -> StreamSearcher<'r> {
+        return StreamSearcher {
+            matcher: Matcher::Regex(regex),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        };
+    }
+
+    // Searches with a DFA loaded by DenseDfa::from_bytes
+    pub fn with_dense_dfa(dfa: &'r DenseDfa) -> StreamSearcher<'r> {
+        return StreamSearcher {
+            matcher: Matcher::DenseDfa(dfa),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        };
+    }
+
+    pub fn chunk_size(&mut self, chunk_size: usize) -> &mut StreamSearcher<'r> {
+        self.chunk_size = chunk_size.max(MIN_CHUNK_SIZE);
+        return self;
+    }
+
+    // Writes every line of the input with a match in it to the writer, each followed by a newline, and returns how
+    // many there were. The input has to be UTF-8, the same as if it had been read with read_to_string.
+    pub fn search<R: Read, W: Write>(&self, mut reader: R, writer: W) -> io::Result<usize> {
+        let mut chunk = vec![0; self.chunk_size];
+        // The first bytes of a char split across chunks, kept at the front of the chunk
+        let mut carried = 0;
+        let mut line = Line::new(self.matcher, writer);
+
+        loop {
+            let read = match reader.read(&mut chunk[carried..]) {
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            if read == 0 {
+                if carried > 0 {
+                    return Err(invalid_utf8());
+                }
+
+                break;
+            }
+
+            let filled = carried + read;
+
+            let valid = match std::str::from_utf8(&chunk[..filled]) {
+                Ok(text) => text.len(),
+                // Only the end of the chunk is cut off part way through a char, anything else is invalid
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => return Err(invalid_utf8()),
+            };
+
+            let text = std::str::from_utf8(&chunk[..valid]).unwrap();
+            let mut pieces = text.split('\n');
+
+            // The last piece is the start of a line that carries on into the next chunk
+            line.push(pieces.next().unwrap())?;
+
+            for piece in pieces {
+                line.end()?;
+                line.push(piece)?;
+            }
+
+            chunk.copy_within(valid..filled, 0);
+            carried = filled - valid;
+        }
+
+        return line.finish();
+    }
+}
+
+impl<'r, W: Write> Line<'r, W> {
+    fn new(matcher: Matcher<'r>, writer: W) -> Line<'r, W> {
+        let mut state = LineState::new(matcher);
+
+        return Line {
+            matcher,
+            matched: state.has_matched(),
+            state,
+            writer,
+            buffer: String::new(),
+            started: false,
+            pending_cr: false,
+            num_of_matches: 0,
+        };
+    }
+
+    fn push(&mut self, piece: &str) -> io::Result<()> {
+        if piece.is_empty() {
+            return Ok(());
+        }
+
+        self.started = true;
+
+        // A \r is only part of the line if it isn't the last thing before the \n, which we can't know until
+        // something else turns up
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.feed("\r")?;
+        }
+
+        let piece = match piece.strip_suffix('\r') {
+            Some(piece) => {
+                self.pending_cr = true;
+                piece
+            }
+            None => piece,
+        };
+
+        return self.feed(piece);
+    }
+
+    fn feed(&mut self, text: &str) -> io::Result<()> {
+        if self.matched {
+            return self.writer.write_all(text.as_bytes());
+        }
+
+        self.buffer.push_str(text);
+
+        if self.state.feed(text) {
+            self.matched = true;
+            self.writer.write_all(self.buffer.as_bytes())?;
+            self.buffer.clear();
+        }
+
+        return Ok(());
+    }
+
+    // The line's been cut off by a newline
+    fn end(&mut self) -> io::Result<()> {
+        if !self.matched && self.state.finish(&self.buffer) {
+            self.matched = true;
+            self.writer.write_all(self.buffer.as_bytes())?;
+        }
+
+        if self.matched {
+            self.writer.write_all(b"\n")?;
+            self.num_of_matches += 1;
+        }
+
+        self.state = LineState::new(self.matcher);
+        self.matched = self.state.has_matched();
+        self.buffer.clear();
+        self.started = false;
+        self.pending_cr = false;
+
+        return Ok(());
+    }
+
+    fn finish(mut self) -> io::Result<usize> {
+        // A \r right at the end of the input is part of the last line, since there's no \n after it
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.feed("\r")?;
+        }
+
+        if self.started {
+            self.end()?;
+        }
+
+        self.writer.flush()?;
+
+        return Ok(self.num_of_matches);
+    }
+}
+
+impl<'r> LineState<'r> {
+    fn new(matcher: Matcher<'r>) -> LineState<'r> {
+        return match matcher {
+            Matcher::Regex(regex) => LineState::Regex(regex.line_search()),
+            Matcher::DenseDfa(dfa) => LineState::DenseDfa {
+                dfa,
+                state: dfa.get_start(),
+                matched: dfa.is_accepting(dfa.get_start()),
+            },
+        };
+    }
+
+    fn has_matched(&mut self) -> bool {
+        return self.feed("");
+    }
+
+    fn feed(&mut self, text: &str) -> bool {
+        return match self {
+            LineState::Regex(search) => search.feed(text),
+            LineState::DenseDfa {
+                dfa,
+                state,
+                matched,
+            } => {
+                for c in text.chars() {
+                    if *matched {
+                        break;
+                    }
+
+                    *state = dfa.next_state(*state, c);
+                    *matched = dfa.is_accepting(*state);
+                }
+
+                *matched
+            }
+        };
+    }
+
+    fn finish(&mut self, line: &str) -> bool {
+        return match self {
+            LineState::Regex(search) => search.finish(line),
+            LineState::DenseDfa { matched, .. } => *matched,
+        };
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    return io::Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search_lines;
+
+    fn search(regex: &Regex, input: &str, chunk_size: usize) -> (String, usize) {
+        let mut output: Vec<u8> = Vec::new();
+        let count = StreamSearcher::new(regex)
+            .chunk_size(chunk_size)
+            .search(input.as_bytes(), &mut output)
+            .unwrap();
+
+        return (String::from_utf8(output).unwrap(), count);
+    }
+
+    fn expected(regex: &Regex, input: &str) -> String {
+        return search_lines(regex, input)
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+    }
+
+    #[test]
+    fn given_any_chunk_size_when_streaming_should_find_the_same_lines_as_searching_the_whole_input()
+    {
+        let input = "error: disk full\r\nok\nérror: ünicode\r\n\nwarning\rerror: net down\nerror\r";
+        let queries = ["error", "(é|e)rror: (d|ü)", "o?k\r", "g\rerror", "\r", "x?"];
+
+        for query in queries {
+            let regex = Regex::new(query, false).unwrap();
+
+            for chunk_size in [1, 2, 3, 5, 8, 64, DEFAULT_CHUNK_SIZE] {
+                let (output, count) = search(&regex, input, chunk_size);
+
+                assert_eq!(output, expected(&regex, input), "{} {}", query, chunk_size);
+                assert_eq!(count, output.lines().count(), "{} {}", query, chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn given_literal_patterns_when_streaming_should_search_each_whole_line() {
+        let input = "the cat sat\non the mat\nwith a dog";
+        let regex = Regex::fixed_strings(&["cat", "dog"], false).unwrap();
+
+        assert_eq!(search(&regex, input, 4).0, "the cat sat\nwith a dog\n");
+    }
+
+    #[test]
+    fn given_dense_dfa_when_streaming_should_find_the_same_lines() {
+        let dfa = DenseDfa::new(&["ab+c"], false).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+
+        let count = StreamSearcher::with_dense_dfa(&dfa)
+            .chunk_size(3)
+            .search("xabbbc\nac\nabc".as_bytes(), &mut output)
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "xabbbc\nabc\n");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn given_invalid_utf8_when_streaming_should_give_an_error() {
+        let regex = Regex::new("a", false).unwrap();
+        let searcher = StreamSearcher::new(&regex);
+
+        for input in [&b"a\n\xff\n"[..], &b"a\n\xc3"[..]] {
+            let error = searcher.search(input, io::sink()).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+    }
+}